@@ -0,0 +1,80 @@
+//! Cost of generating a flake id with the atomic generator behind
+//! `flake_id!`, against a `Mutex<Flaken>` as used before, alone and with
+//! threads contending.
+//!
+//! The default layout allows 4096 ids per millisecond, which either
+//! generator exceeds: the atomic one waits for the next millisecond, while
+//! `Flaken` wraps its sequence into duplicates. Both get 22 sequence bits
+//! here, so that the generators rather than the clock are measured.
+
+use std::{
+    hint::black_box,
+    sync::{Arc, Barrier, Mutex},
+    time::{Duration, Instant},
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use lolibaso::{flake_id, flake_id::FlakeLayout, flaken::Flaken};
+
+flake_id!(BenchId);
+
+const THREADS: usize = 4;
+
+fn mutex_next(flaken: &Mutex<Flaken>) -> i64 {
+    flaken.lock().unwrap().next() as i64
+}
+
+/// Runs `iters` calls of `f` spread over `THREADS` threads, timing the
+/// slowest thread.
+fn contended(iters: u64, f: impl Fn() -> i64 + Send + Sync + 'static) -> Duration {
+    let f = Arc::new(f);
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (f, barrier) = (f.clone(), barrier.clone());
+            std::thread::spawn(move || {
+                barrier.wait();
+                let start = Instant::now();
+                for _ in 0..iters / THREADS as u64 {
+                    black_box(f());
+                }
+                start.elapsed()
+            })
+        })
+        .collect();
+    threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .max()
+        .unwrap_or_default()
+}
+
+fn generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flake_id");
+
+    let layout = FlakeLayout {
+        timestamp_bits: 40,
+        node_bits: 2,
+        ..Default::default()
+    };
+    BenchId::generator().init(layout).unwrap();
+    let flaken = Flaken::default().bitwidths(40, 2);
+    let flaken = Arc::new(Mutex::new(flaken));
+    group.bench_function("mutex", |b| b.iter(|| black_box(mutex_next(&flaken))));
+    group.bench_function("atomic", |b| b.iter(|| black_box(BenchId::generate())));
+
+    group.bench_function("mutex_contended", |b| {
+        b.iter_custom(|iters| {
+            let flaken = flaken.clone();
+            contended(iters, move || mutex_next(&flaken))
+        })
+    });
+    group.bench_function("atomic_contended", |b| {
+        b.iter_custom(|iters| contended(iters, || BenchId::generate().0))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, generate);
+criterion_main!(benches);
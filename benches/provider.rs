@@ -0,0 +1,45 @@
+//! Per-request cost of providing a use case, with and without an
+//! application container. The container starts empty, as in a handler
+//! whose graph was not built at startup.
+
+use std::{hint::black_box, sync::Arc};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use lolibaso::Provider;
+use lolibaso::provider::{Provider, SyncProviderContext};
+
+/// Stands in for a connection pool or client that is expensive to set up.
+#[derive(Provider)]
+struct Pool {
+    #[provider(with = (0..256).map(|idx| format!("conn-{idx}")).collect())]
+    _slots: Vec<String>,
+}
+
+#[derive(Provider)]
+struct UserRepo {
+    _pool: Arc<Pool>,
+}
+
+#[derive(Provider)]
+struct CreateUser {
+    _repo: Arc<UserRepo>,
+    _pool: Arc<Pool>,
+}
+
+fn per_request(c: &mut Criterion) {
+    let mut group = c.benchmark_group("per_request");
+
+    group.bench_function("provide", |b| {
+        b.iter(|| black_box(CreateUser::provide().unwrap()))
+    });
+
+    let container = Arc::new(SyncProviderContext::new());
+    group.bench_function("container_scope", |b| {
+        b.iter(|| black_box(container.scope().build::<CreateUser>().unwrap()))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, per_request);
+criterion_main!(benches);
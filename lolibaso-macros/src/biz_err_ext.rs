@@ -134,9 +134,7 @@ fn require_lit_str(expr: &syn::Expr) -> syn::Result<&syn::LitStr> {
 fn require_literal(expr: &syn::Expr) -> syn::Result<&syn::Lit> {
     match expr {
         syn::Expr::Lit(expr_lit) => Ok(&expr_lit.lit),
-        _ => {
-            return Err(syn::Error::new_spanned(expr, "expected literal"));
-        }
+        _ => Err(syn::Error::new_spanned(expr, "expected literal")),
     }
 }
 
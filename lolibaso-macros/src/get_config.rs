@@ -1,58 +1,239 @@
-use quote::{ToTokens, quote, quote_spanned};
+use proc_macro2::{Literal, TokenStream};
+use quote::{ToTokens, format_ident, quote, quote_spanned};
 use syn::{Field, Ident, Token, parse::Parse, punctuated::Punctuated, spanned::Spanned};
 
 pub struct GetConfig {
     ident: Ident,
     fields: FieldsNamed,
+    /// Whether `#[config(root)]` asks for a `ConfigRoot` impl.
+    root: bool,
 }
 type FieldsNamed = Punctuated<Field, Token![,]>;
 
 impl Parse for GetConfig {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let derive_input = syn::DeriveInput::parse(input)?;
+        let mut root = false;
+        for attr in &derive_input.attrs {
+            if attr.path().is_ident("config") {
+                let ident = attr.parse_args::<Ident>()?;
+                match ident.to_string().as_str() {
+                    "root" => root = true,
+                    _ => return Err(syn::Error::new_spanned(attr, "unknown config attribute")),
+                }
+            }
+        }
         match derive_input.data {
             syn::Data::Struct(data_struct) => match data_struct.fields {
                 syn::Fields::Named(fields_named) => Ok(Self {
                     ident: derive_input.ident,
                     fields: fields_named.named,
+                    root,
                 }),
-                _ => {
-                    return Err(syn::Error::new_spanned(
-                        &derive_input.ident,
-                        "expected named fields",
-                    ));
-                }
-            },
-            _ => {
-                return Err(syn::Error::new_spanned(
+                _ => Err(syn::Error::new_spanned(
                     &derive_input.ident,
-                    "`GetConfig` expected struct",
-                ));
-            }
+                    "expected named fields",
+                )),
+            },
+            _ => Err(syn::Error::new_spanned(
+                &derive_input.ident,
+                "`GetConfig` expected struct",
+            )),
         }
     }
 }
 
+struct ConfigField<'a> {
+    index: usize,
+    name: &'a Ident,
+    ty: &'a syn::Type,
+    /// The type path with generic arguments stripped, used to invoke the
+    /// section macro of a `#[config(flatten)]` field.
+    flatten: Option<syn::Path>,
+}
+
 impl GetConfig {
     pub fn expand(&self) -> syn::Result<proc_macro2::TokenStream> {
-        Ok(quote! {#self})
-    }
-}
+        let mut sections = vec![];
+        let mut seen: Vec<(String, &syn::Type)> = vec![];
+        for (index, field) in self.fields.iter().enumerate() {
+            let mut skip = false;
+            let mut flatten = false;
+            for attr in &field.attrs {
+                if attr.path().is_ident("config") {
+                    let ident = attr.parse_args::<Ident>()?;
+                    match ident.to_string().as_str() {
+                        "skip" => skip = true,
+                        "flatten" => flatten = true,
+                        _ => return Err(syn::Error::new_spanned(attr, "unknown config attribute")),
+                    }
+                }
+            }
+            if skip {
+                continue;
+            }
+
+            let ty_str = field.ty.to_token_stream().to_string();
+            if seen.iter().any(|(seen, _)| *seen == ty_str) {
+                return Err(syn::Error::new_spanned(
+                    &field.ty,
+                    format!(
+                        "`GetConfig<{ty_str}>` is ambiguous: another field has the same type. \
+                         Mark one of them with `#[config(skip)]`"
+                    ),
+                ));
+            }
+            seen.push((ty_str, &field.ty));
+
+            let flatten = match (flatten, &field.ty) {
+                (false, _) => None,
+                (true, syn::Type::Path(path)) => {
+                    let mut path = path.path.clone();
+                    for seg in &mut path.segments {
+                        seg.arguments = syn::PathArguments::None;
+                    }
+                    Some(path)
+                }
+                (true, ty) => {
+                    return Err(syn::Error::new_spanned(
+                        ty,
+                        "`#[config(flatten)]` expects a struct deriving `GetConfig`",
+                    ));
+                }
+            };
+
+            sections.push(ConfigField {
+                index,
+                name: field.ident.as_ref().unwrap(),
+                ty: &field.ty,
+                flatten,
+            });
+        }
 
-impl ToTokens for GetConfig {
-    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let this_ty = &self.ident;
-        for field in &self.fields {
-            let ty = &field.ty;
-            let name = field.ident.as_ref().unwrap();
-            let stream = quote_spanned! { field.span() =>
+        let mut tokens = TokenStream::new();
+        for field in &sections {
+            let ty = field.ty;
+            let name = field.name;
+            tokens.extend(quote_spanned! { ty.span() =>
                 impl ::lolibaso::configs::GetConfig<#ty> for #this_ty {
                     fn get_config(&self) -> &#ty {
                         &self.#name
                     }
                 }
-            };
-            tokens.extend(stream);
+            });
+        }
+
+        for (index, field) in self.fields.iter().enumerate() {
+            let ty = &field.ty;
+            let index = Literal::usize_unsuffixed(index);
+            tokens.extend(quote! {
+                impl ::lolibaso::configs::ConfigField<#index> for #this_ty {
+                    type Type = #ty;
+                }
+            });
+        }
+
+        let flattened = sections.iter().filter_map(|f| {
+            let path = f.flatten.as_ref()?;
+            let (ty, name) = (f.ty, f.name);
+            Some(quote! {
+                #path! { @get_config root = #this_ty, ty = #ty, path = [#name] }
+            })
+        });
+        tokens.extend(quote!(#(#flattened)*));
+
+        tokens.extend(self.expand_section_macro(&sections));
+        tokens.extend(self.expand_config_sections(&sections));
+        if self.root {
+            let this_ty = &self.ident;
+            tokens.extend(quote! {
+                impl ::lolibaso::configs::ConfigRoot for #this_ty {
+                    fn get_section(&self, type_id: ::std::any::TypeId) -> Option<&dyn ::std::any::Any> {
+                        ::lolibaso::configs::ConfigSections::get_section(self, type_id)
+                    }
+                }
+            });
+        }
+        Ok(tokens)
+    }
+
+    /// Emits a hidden macro, importable under the struct's own name, that
+    /// implements `GetConfig` for the sections of this struct on a root
+    /// config that flattens it.
+    ///
+    /// Section types are named by projection through `ConfigField`, so they
+    /// need not be in scope at the root. Nested flattened structs are invoked
+    /// by the path written in their parent, from the root's module.
+    fn expand_section_macro(&self, sections: &[ConfigField]) -> TokenStream {
+        let this_ty = &self.ident;
+        let macro_ident = format_ident!("__get_config_{}", this_ty);
+
+        let impls = sections.iter().map(|f| {
+            let index = Literal::usize_unsuffixed(f.index);
+            let name = f.name;
+            quote! {
+                impl ::lolibaso::configs::GetConfig<<$ty as ::lolibaso::configs::ConfigField<#index>>::Type> for $root {
+                    fn get_config(&self) -> &<$ty as ::lolibaso::configs::ConfigField<#index>>::Type {
+                        &self $(.$path)* .#name
+                    }
+                }
+            }
+        });
+        let nested = sections.iter().filter_map(|f| {
+            let path = f.flatten.as_ref()?;
+            let index = Literal::usize_unsuffixed(f.index);
+            let name = f.name;
+            Some(quote! {
+                #path! {
+                    @get_config root = $root,
+                    ty = <$ty as ::lolibaso::configs::ConfigField<#index>>::Type,
+                    path = [$($path)* #name]
+                }
+            })
+        });
+
+        quote! {
+            #[doc(hidden)]
+            #[allow(unused_macros)]
+            macro_rules! #macro_ident {
+                (@get_config root = $root:ty, ty = $ty:ty, path = [$($path:ident)*]) => {
+                    #(#impls)*
+                    #(#nested)*
+                };
+            }
+            #[doc(hidden)]
+            #[allow(unused_imports)]
+            pub(crate) use #macro_ident as #this_ty;
+        }
+    }
+
+    fn expand_config_sections(&self, sections: &[ConfigField]) -> TokenStream {
+        let this_ty = &self.ident;
+        let tys = sections.iter().map(|f| f.ty);
+        let flattened = sections
+            .iter()
+            .filter(|f| f.flatten.is_some())
+            .map(|f| f.name);
+        quote! {
+            impl ::lolibaso::configs::ConfigSections for #this_ty {
+                fn get_section(&self, type_id: ::std::any::TypeId) -> Option<&dyn ::std::any::Any> {
+                    if type_id == ::std::any::TypeId::of::<Self>() {
+                        return Some(self);
+                    }
+                    #(
+                        if type_id == ::std::any::TypeId::of::<#tys>() {
+                            return Some(<Self as ::lolibaso::configs::GetConfig<#tys>>::get_config(self));
+                        }
+                    )*
+                    #(
+                        if let Some(section) = ::lolibaso::configs::ConfigSections::get_section(&self.#flattened, type_id) {
+                            return Some(section);
+                        }
+                    )*
+                    None
+                }
+            }
         }
     }
 }
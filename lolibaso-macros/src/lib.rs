@@ -6,7 +6,11 @@ mod http_requst;
 mod http_response;
 mod init;
 mod provider;
+mod validate_config;
 
+/// Derives `Provider`. `Arc<Self>` built in a `SyncProviderContext` scope is
+/// shared by the container when `Self` is `Send + Sync`; for generic types it
+/// is always kept in the scope, since the bounds can not be checked there.
 #[proc_macro_derive(Provider, attributes(provider))]
 pub fn derive_provider(input: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(input as syn::DeriveInput);
@@ -15,6 +19,15 @@ pub fn derive_provider(input: TokenStream) -> TokenStream {
         .into()
 }
 
+/// Derives `AsyncProvider`, sharing `Arc<Self>` like the `Provider` derive.
+#[proc_macro_derive(AsyncProvider, attributes(provider))]
+pub fn derive_async_provider(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+    provider::expand_async(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
 #[allow(non_snake_case)]
 #[proc_macro_attribute]
 pub fn BizErrExt(_args: TokenStream, item: TokenStream) -> TokenStream {
@@ -57,7 +70,15 @@ pub fn InitFunction(args: TokenStream, item: TokenStream) -> TokenStream {
     stream
 }
 
-#[proc_macro_derive(GetConfig)]
+#[proc_macro_derive(ValidateConfig, attributes(validate))]
+pub fn derive_validate_config(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+    validate_config::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(GetConfig, attributes(config))]
 pub fn derive_get_config(input: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(input as get_config::GetConfig);
     input
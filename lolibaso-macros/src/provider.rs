@@ -1,3 +1,4 @@
+use convert_case::{Case, Casing};
 use proc_macro2::{Span, TokenStream};
 use quote::{ToTokens, quote};
 use syn::{
@@ -6,14 +7,64 @@ use syn::{
 };
 
 pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    expand_with(input, false)
+}
+
+pub fn expand_async(input: DeriveInput) -> syn::Result<TokenStream> {
+    expand_with(input, true)
+}
+
+fn expand_with(input: DeriveInput, is_async: bool) -> syn::Result<TokenStream> {
     let struct_ident = &input.ident;
-    let ds = match &input.data {
-        syn::Data::Struct(ds) => ds,
-        syn::Data::Enum(_) => {
-            return Err(syn::Error::new_spanned(
-                &input.ident,
-                "Enums are not supported to derive Provider",
-            ));
+
+    let mut dispose = false;
+    let mut select = None;
+    for attr in &input.attrs {
+        if attr.path().is_ident("provider") {
+            let arg = attr.parse_args::<AttrArg>()?;
+            match (arg.name.to_string().as_str(), arg.value) {
+                ("dispose", None) => dispose = true,
+                ("select", Some(expr)) => select = Some(expr),
+                _ => return Err(syn::Error::new_spanned(attr, "unknown Provider attribute")),
+            }
+        }
+    }
+    let register_dispose = dispose.then(|| {
+        quote! {
+            ctx.register_dispose_of::<Self>();
+        }
+    });
+
+    let (body, fields, dependencies) = match &input.data {
+        syn::Data::Struct(ds) => {
+            if let Some(select) = select {
+                return Err(syn::Error::new_spanned(
+                    select,
+                    "`#[provider(select = ...)]` is only supported on enums",
+                ));
+            }
+            let fields = provider_fields(&ds.fields, is_async)?;
+            let body = quote! {
+                #struct_ident {
+                    #(#fields),*
+                }
+            };
+            let dependencies: Vec<_> = fields.iter().map(|f| f.dependency(None)).collect();
+            (body, fields, dependencies)
+        }
+        syn::Data::Enum(de) => {
+            let mut variants = vec![];
+            for v in &de.variants {
+                variants.push(ProviderVariant::from_syn(v, is_async)?);
+            }
+            let body = expand_enum_body(struct_ident, &variants, select)?;
+            let mut fields = vec![];
+            let mut dependencies = vec![];
+            for v in variants {
+                dependencies.extend(v.fields.iter().map(|f| f.dependency(Some(&v.ident))));
+                fields.extend(v.fields);
+            }
+            (body, fields, dependencies)
         }
         syn::Data::Union(_) => {
             return Err(syn::Error::new_spanned(
@@ -22,11 +73,6 @@ pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
             ));
         }
     };
-
-    let mut fields = vec![];
-    for (idx, f) in ds.fields.iter().enumerate() {
-        fields.push(ProviderField::from_syn(f, idx)?);
-    }
     let (impl_generics, ty_generics, where_clause) = &input.generics.split_for_impl();
 
     let mut where_clause = where_clause.cloned().unwrap_or_else(|| syn::WhereClause {
@@ -38,13 +84,67 @@ pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
         where_clause.predicates.push(f.impl_bound());
     }
 
-    let stream = quote! {
-        impl #impl_generics lolibaso::provider::Provider for #struct_ident #ty_generics #where_clause {
-            fn build(ctx: &mut lolibaso::provider::ProviderContext) -> anyhow::Result<Self> {
-                let this = #struct_ident {
-                    #(#fields),*
-                };
-                Ok(this)
+    let node = quote! {
+        fn node() -> lolibaso::provider::graph::ProviderNode {
+            lolibaso::provider::graph::ProviderNode::new::<Self>(vec![
+                #(#dependencies),*
+            ])
+        }
+    };
+
+    let share_arc_async = quote! {
+        fn share_arc(
+            this: ::std::sync::Arc<Self>,
+            container: &lolibaso::provider::SyncProviderContext,
+        ) -> ::std::sync::Arc<Self> {
+            #[allow(unused_imports)]
+            use lolibaso::provider::share::{ShareLocal as _, ShareSync as _};
+            (&&lolibaso::provider::share::Share::<::std::sync::Arc<Self>>::new()).share_fn()(this, container)
+        }
+    };
+
+    let stream = if is_async {
+        quote! {
+            impl #impl_generics lolibaso::provider::AsyncProvider for #struct_ident #ty_generics #where_clause {
+                async fn build_async(ctx: &mut lolibaso::provider::ProviderContext) -> anyhow::Result<Self> {
+                    let this = #body;
+                    #register_dispose
+                    Ok(this)
+                }
+
+                #node
+
+                #share_arc_async
+            }
+        }
+    } else {
+        quote! {
+            impl #impl_generics lolibaso::provider::Provider for #struct_ident #ty_generics #where_clause {
+                fn build(ctx: &mut lolibaso::provider::ProviderContext) -> anyhow::Result<Self> {
+                    let this = #body;
+                    #register_dispose
+                    Ok(this)
+                }
+
+                #node
+
+                fn share_arc(
+                    container: &lolibaso::provider::SyncProviderContext,
+                    build: &mut dyn FnMut() -> anyhow::Result<::std::sync::Arc<Self>>,
+                ) -> anyhow::Result<::std::sync::Arc<Self>> {
+                    #[allow(unused_imports)]
+                    use lolibaso::provider::share::{ShareLocal as _, ShareSync as _};
+                    (&&lolibaso::provider::share::Share::<::std::sync::Arc<Self>>::new()).build_fn()(container, build)
+                }
+
+                fn share_singleton(
+                    container: &lolibaso::provider::SyncProviderContext,
+                    build: &mut dyn FnMut() -> anyhow::Result<Self>,
+                ) -> anyhow::Result<Self> {
+                    #[allow(unused_imports)]
+                    use lolibaso::provider::share::{ShareLocal as _, ShareSync as _};
+                    (&&lolibaso::provider::share::Share::<Self>::new()).build_fn()(container, build)
+                }
             }
         }
     };
@@ -52,9 +152,166 @@ pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
     Ok(stream)
 }
 
+fn provider_fields(fields: &syn::Fields, is_async: bool) -> syn::Result<Vec<ProviderField>> {
+    let mut out = vec![];
+    for (idx, f) in fields.iter().enumerate() {
+        let field = ProviderField::from_syn(f, idx)?;
+        if !is_async && matches!(field.kind, FieldKind::Async) {
+            return Err(syn::Error::new_spanned(
+                f,
+                "`#[provider(async)]` fields require `#[derive(AsyncProvider)]`",
+            ));
+        }
+        out.push(field);
+    }
+    Ok(out)
+}
+
+/// Builds the variant chosen by the enum's `select` expression, or else the
+/// first variant whose `when` predicate holds. A variant without `when` is
+/// the fallback.
+fn expand_enum_body(
+    enum_ident: &Ident,
+    variants: &[ProviderVariant],
+    select: Option<syn::Expr>,
+) -> syn::Result<TokenStream> {
+    if let Some(select) = select {
+        if let Some(when) = variants.iter().find_map(|v| v.when.as_ref()) {
+            return Err(syn::Error::new_spanned(
+                when,
+                "`#[provider(when = ...)]` cannot be combined with `#[provider(select = ...)]`",
+            ));
+        }
+        let keys: Vec<_> = variants.iter().map(|v| &v.key).collect();
+        for (idx, key) in keys.iter().enumerate() {
+            if keys[..idx].iter().any(|k| k.value() == key.value()) {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    format!("duplicate variant key `{}`", key.value()),
+                ));
+            }
+        }
+        let expected = keys
+            .iter()
+            .map(|k| k.value())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let builds = variants.iter().map(ProviderVariant::build);
+        return Ok(quote! {
+            match lolibaso::provider::select_variant(ctx, #select)?.as_str() {
+                #(#keys => #builds,)*
+                selected => {
+                    ::anyhow::bail!("Provider::build: unknown variant selected. type = {}. selected = {}. expected = {}", stringify!(#enum_ident), selected, #expected)
+                }
+            }
+        });
+    }
+
+    if variants.iter().all(|v| v.when.is_none()) {
+        return Err(syn::Error::new_spanned(
+            enum_ident,
+            "enum providers require `#[provider(select = ...)]` on the enum or `#[provider(when = ...)]` on its variants",
+        ));
+    }
+
+    let mut branches = vec![];
+    for (idx, v) in variants.iter().enumerate() {
+        let build = v.build();
+        match &v.when {
+            Some(when) => branches.push(quote! {
+                if lolibaso::provider::variant_when(ctx, #when) { #build }
+            }),
+            None => {
+                if let Some(unreachable) = variants.get(idx + 1) {
+                    return Err(syn::Error::new_spanned(
+                        &unreachable.ident,
+                        format!(
+                            "variant is never selected: `{}` without `#[provider(when = ...)]` is the fallback and must come last",
+                            v.ident
+                        ),
+                    ));
+                }
+                branches.push(quote!({ #build }));
+                return Ok(quote!(#(#branches)else*));
+            }
+        }
+    }
+    branches.push(quote! {
+        {
+            ::anyhow::bail!("Provider::build: no variant selected. type = {}", stringify!(#enum_ident))
+        }
+    });
+    Ok(quote!(#(#branches)else*))
+}
+
+struct ProviderVariant {
+    ident: Ident,
+    /// Matched against the value returned by the enum's `select` expression.
+    key: syn::LitStr,
+    when: Option<syn::Expr>,
+    fields: Vec<ProviderField>,
+}
+
+impl ProviderVariant {
+    fn from_syn(v: &syn::Variant, is_async: bool) -> syn::Result<Self> {
+        let mut key = syn::LitStr::new(&v.ident.to_string().to_case(Case::Snake), v.ident.span());
+        let mut when = None;
+        for attr in &v.attrs {
+            if attr.path().is_ident("provider") {
+                let arg = attr.parse_args::<AttrArg>()?;
+                match (arg.name.to_string().as_str(), arg.value) {
+                    (
+                        "rename",
+                        Some(syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(lit),
+                            ..
+                        })),
+                    ) => key = lit,
+                    ("when", Some(expr)) => when = Some(expr),
+                    _ => return Err(syn::Error::new_spanned(attr, "unknown Provider attribute")),
+                }
+            }
+        }
+
+        Ok(ProviderVariant {
+            ident: v.ident.clone(),
+            key,
+            when,
+            fields: provider_fields(&v.fields, is_async)?,
+        })
+    }
+
+    fn build(&self) -> TokenStream {
+        let ident = &self.ident;
+        let fields = &self.fields;
+        quote! {
+            Self::#ident {
+                #(#fields),*
+            }
+        }
+    }
+}
+
+/// `name` or `name = expr` inside `#[provider(...)]`.
+struct AttrArg {
+    name: Ident,
+    value: Option<syn::Expr>,
+}
+
+impl Parse for AttrArg {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let name = input.parse::<Ident>()?;
+        let value = match input.parse::<Option<Token![=]>>()? {
+            Some(_) => Some(input.parse::<syn::Expr>()?),
+            None => None,
+        };
+        Ok(AttrArg { name, value })
+    }
+}
+
 #[derive(Debug)]
 struct ProviderField {
-    name: syn::Ident,
+    name: syn::Member,
     ty: syn::Type,
     kind: FieldKind,
 }
@@ -62,11 +319,51 @@ struct ProviderField {
 #[derive(Debug)]
 enum FieldKind {
     Provider,
+    Async,
+    Bound(BoundWrapper, syn::Type),
     Instance,
+    Named(Qualifier),
+    /// Holds the inner type for `Arc<T>` fields.
+    Config(Option<syn::Type>),
     Default,
     With(syn::Expr),
 }
 
+/// Smart pointer around a trait object field, resolved through the bindings
+/// registered in the `ProviderContext`.
+#[derive(Debug, Clone, Copy)]
+enum BoundWrapper {
+    Box,
+    Arc,
+    Rc,
+}
+
+impl BoundWrapper {
+    /// Matches `Box<dyn Trait>`, `Arc<dyn Trait>` and `Rc<dyn Trait>`,
+    /// returning the wrapper and the trait object type.
+    fn detect(ty: &syn::Type) -> Option<(Self, syn::Type)> {
+        let syn::Type::Path(path) = ty else {
+            return None;
+        };
+        let last = path.path.segments.last()?;
+        let wrapper = match last.ident.to_string().as_str() {
+            "Box" => BoundWrapper::Box,
+            "Arc" => BoundWrapper::Arc,
+            "Rc" => BoundWrapper::Rc,
+            _ => return None,
+        };
+        let syn::PathArguments::AngleBracketed(args) = &last.arguments else {
+            return None;
+        };
+        match args.args.first()? {
+            syn::GenericArgument::Type(inner @ syn::Type::TraitObject(_)) => {
+                Some((wrapper, inner.clone()))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl ToTokens for ProviderField {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let name = &self.name;
@@ -76,27 +373,100 @@ impl ToTokens for ProviderField {
         Token![:](Span::call_site()).to_tokens(tokens);
         match &self.kind {
             FieldKind::Provider => {
-                let path: syn::Expr = parse_quote!(lolibaso::provider::Provider::build(ctx)?);
+                let path: syn::Expr =
+                    parse_quote!(lolibaso::provider::ProviderContext::build::<#ty>(ctx)?);
+                path.to_tokens(tokens);
+            }
+            FieldKind::Async => {
+                let path: syn::Expr = parse_quote!(
+                    lolibaso::provider::ProviderContext::build_async::<#ty>(ctx).await?
+                );
+                path.to_tokens(tokens);
+            }
+            FieldKind::Bound(wrapper, inner) => {
+                let method = match wrapper {
+                    BoundWrapper::Box => quote!(build_boxed),
+                    BoundWrapper::Arc => quote!(build_arc),
+                    BoundWrapper::Rc => quote!(build_rc),
+                };
+                let path: syn::Expr =
+                    parse_quote!(lolibaso::provider::ProviderContext::#method::<#inner>(ctx)?);
                 path.to_tokens(tokens);
             }
             FieldKind::Instance => {
                 let expr: syn::Expr = parse_quote! {
-                    match ctx.remove() {
-                        Some(val) => val,
-                        None => {
-                            ::anyhow::bail!("Provider::build: Instance not found. field = {}. type = {}", stringify!(#name), stringify!(#ty))
-                        }
+                    match ctx.build_override::<#ty>() {
+                        Some(val) => val?,
+                        None => match ctx.remove() {
+                            Some(val) => val,
+                            None => {
+                                ::anyhow::bail!("Provider::build: Instance not found. field = {}. type = {}", stringify!(#name), stringify!(#ty))
+                            }
+                        },
+                    }
+                };
+                expr.to_tokens(tokens);
+            }
+            FieldKind::Named(qualifier) => {
+                let (over, lookup, label) = match qualifier {
+                    Qualifier::Name(name) => (
+                        quote!(ctx.build_named_override::<#ty>(#name)),
+                        quote!(ctx.get_cloned_named::<#ty>(#name)),
+                        quote!(#name),
+                    ),
+                    Qualifier::Tag(tag) => (
+                        quote!(ctx.build_tagged_override::<#tag, #ty>()),
+                        quote!(ctx.get_cloned_tagged::<#tag, #ty>()),
+                        quote!(stringify!(#tag)),
+                    ),
+                };
+                let expr: syn::Expr = parse_quote! {
+                    match #over {
+                        Some(val) => val?,
+                        None => match #lookup {
+                            Some(val) => val,
+                            None => {
+                                ::anyhow::bail!("Provider::build: Named instance not found. field = {}. type = {}. name = {}", stringify!(#name), stringify!(#ty), #label)
+                            }
+                        },
+                    }
+                };
+                expr.to_tokens(tokens);
+            }
+            FieldKind::Config(arc_inner) => {
+                let lookup = match arc_inner {
+                    Some(inner) => quote!(ctx.config_arc::<#inner>()),
+                    None => quote!(ctx.config::<#ty>()),
+                };
+                let expr: syn::Expr = parse_quote! {
+                    match ctx.build_override::<#ty>() {
+                        Some(val) => val?,
+                        None => match #lookup {
+                            Some(val) => val,
+                            None => {
+                                ::anyhow::bail!("Provider::build: Config not found. field = {}. type = {}", stringify!(#name), stringify!(#ty))
+                            }
+                        },
                     }
                 };
                 expr.to_tokens(tokens);
             }
             FieldKind::Default => {
                 let expr: syn::Expr = parse_quote! {
-                    Default::default()
+                    match ctx.build_override::<#ty>() {
+                        Some(val) => val?,
+                        None => Default::default(),
+                    }
                 };
                 expr.to_tokens(tokens);
             }
             FieldKind::With(expr) => {
+                let expr: syn::Expr = parse_quote! {
+                    match ctx.build_override::<#ty>() {
+                        Some(val) => val?,
+                        None => #expr,
+                    }
+                };
                 expr.to_tokens(tokens);
             }
         }
@@ -108,7 +478,7 @@ struct ProvideWithExpr(syn::Expr);
 impl Parse for ProvideWithExpr {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let with = input.parse::<syn::Ident>()?;
-        if with.to_string() != "with" {
+        if with != "with" {
             return Err(syn::Error::new_spanned(with, "expected with"));
         }
         let _eq = input.parse::<syn::Token![=]>()?;
@@ -117,61 +487,173 @@ impl Parse for ProvideWithExpr {
     }
 }
 
+/// `named = "replica"` or `tagged = Replica`.
+struct ProvideNamed(Qualifier);
+
+#[derive(Debug)]
+enum Qualifier {
+    Name(syn::LitStr),
+    /// A marker type, see `ProviderContext::insert_tagged`.
+    Tag(syn::Path),
+}
+
+impl Parse for ProvideNamed {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let kind = input.parse::<syn::Ident>()?;
+        let _eq = input.parse::<syn::Token![=]>()?;
+        if kind == "named" {
+            Ok(ProvideNamed(Qualifier::Name(input.parse()?)))
+        } else if kind == "tagged" {
+            Ok(ProvideNamed(Qualifier::Tag(input.parse()?)))
+        } else {
+            Err(syn::Error::new_spanned(kind, "expected named or tagged"))
+        }
+    }
+}
+
 impl ProviderField {
     fn from_syn(f: &syn::Field, idx: usize) -> syn::Result<Self> {
-        let mut kind = FieldKind::Provider;
+        let mut kind = match BoundWrapper::detect(&f.ty) {
+            Some((wrapper, inner)) => FieldKind::Bound(wrapper, inner),
+            None => FieldKind::Provider,
+        };
         for attr in f.attrs.clone() {
             if attr.path().is_ident("provider") {
                 if let Ok(ProvideWithExpr(expr)) = attr.parse_args::<ProvideWithExpr>() {
                     kind = FieldKind::With(expr);
                     continue;
                 }
+                if let Ok(ProvideNamed(qualifier)) = attr.parse_args::<ProvideNamed>() {
+                    kind = FieldKind::Named(qualifier);
+                    continue;
+                }
+                if attr.parse_args::<Token![async]>().is_ok() {
+                    kind = FieldKind::Async;
+                    continue;
+                }
                 let ident = attr.parse_args::<syn::Ident>()?;
                 let ident = ident.to_string();
                 match ident.as_str() {
                     "instance" => kind = FieldKind::Instance,
+                    "config" => kind = FieldKind::Config(arc_inner(&f.ty)),
                     "default" => kind = FieldKind::Default,
                     _ => return Err(syn::Error::new_spanned(attr, "unknown Provider attribute")),
                 }
             }
         }
-        let ident = match f.ident.clone() {
-            Some(i) => i,
-            None => Ident::new(&idx.to_string(), f.span()),
+        let name = match f.ident.clone() {
+            Some(i) => syn::Member::Named(i),
+            None => syn::Member::Unnamed(syn::Index {
+                index: idx as u32,
+                span: f.span(),
+            }),
         };
 
         Ok(ProviderField {
-            name: ident,
+            name,
             ty: f.ty.clone(),
             kind,
         })
     }
 
+    /// `variant` prefixes the field label for enum providers.
+    fn dependency(&self, variant: Option<&Ident>) -> TokenStream {
+        let name = &self.name;
+        let field = match variant {
+            Some(variant) => {
+                let label = format!("{variant}.{}", name.to_token_stream());
+                quote!(#label)
+            }
+            None => quote!(stringify!(#name)),
+        };
+        let ty = &self.ty;
+        let (kind, node) = match &self.kind {
+            FieldKind::Provider => (
+                quote!(Provider),
+                quote!(Some(<#ty as lolibaso::provider::Provider>::node)),
+            ),
+            FieldKind::Async => (
+                quote!(Async),
+                quote!(Some(<#ty as lolibaso::provider::AsyncProvider>::node)),
+            ),
+            FieldKind::Bound(..) => (quote!(Bound), quote!(None)),
+            FieldKind::Instance => (quote!(Instance), quote!(None)),
+            FieldKind::Named(_) => (quote!(Named), quote!(None)),
+            FieldKind::Config(_) => (quote!(Config), quote!(None)),
+            FieldKind::Default => (quote!(Default), quote!(None)),
+            FieldKind::With(_) => (quote!(With), quote!(None)),
+        };
+
+        quote! {
+            lolibaso::provider::graph::Dependency {
+                field: #field,
+                type_name: ::std::any::type_name::<#ty>(),
+                kind: lolibaso::provider::graph::DependencyKind::#kind,
+                node: #node,
+            }
+        }
+    }
+
     fn impl_bound(&self) -> syn::WherePredicate {
+        let mut bounded_ty = Clone::clone(&self.ty);
         let mut bounds = vec![];
         match &self.kind {
             FieldKind::Provider => {
                 bounds.push(parse_quote!(::lolibaso::provider::Provider));
             }
-            FieldKind::Instance => {
+            FieldKind::Async => {
+                bounds.push(parse_quote!(::lolibaso::provider::AsyncProvider));
+            }
+            FieldKind::Bound(..) | FieldKind::Instance => {
+                bounds.push(lifetime_bound("'static"));
+            }
+            FieldKind::Named(_) => {
+                bounds.push(trait_bound(parse_quote!(Clone)));
+                bounds.push(lifetime_bound("'static"));
+            }
+            FieldKind::Config(arc_inner) => {
+                if let Some(inner) = arc_inner {
+                    bounded_ty = inner.clone();
+                }
+                bounds.push(trait_bound(parse_quote!(Clone)));
                 bounds.push(lifetime_bound("'static"));
             }
             FieldKind::Default => {
                 bounds.push(trait_bound(parse_quote!(Default)));
                 bounds.push(lifetime_bound("'static"));
             }
-            FieldKind::With(_) => {}
+            FieldKind::With(_) => {
+                bounds.push(lifetime_bound("'static"));
+            }
         };
 
         syn::WherePredicate::Type(PredicateType {
             lifetimes: Default::default(),
-            bounded_ty: Clone::clone(&self.ty),
+            bounded_ty,
             colon_token: Default::default(),
             bounds: Punctuated::from_iter(bounds),
         })
     }
 }
 
+/// Matches `Arc<T>`, returning `T`.
+fn arc_inner(ty: &syn::Type) -> Option<syn::Type> {
+    let syn::Type::Path(path) = ty else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    if last.ident != "Arc" {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &last.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        syn::GenericArgument::Type(inner) => Some(inner.clone()),
+        _ => None,
+    }
+}
+
 fn lifetime_bound(lifetime: &str) -> syn::TypeParamBound {
     syn::TypeParamBound::Lifetime(Lifetime::new(lifetime, Span::call_site()))
 }
@@ -0,0 +1,307 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::DeriveInput;
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let fields = match &input.data {
+        syn::Data::Struct(ds) => match &ds.fields {
+            syn::Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "expected named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`ValidateConfig` expected struct",
+            ));
+        }
+    };
+
+    let mut struct_checks = vec![];
+    for attr in &input.attrs {
+        if attr.path().is_ident("validate") {
+            for rule in parse_rules(attr)? {
+                match rule {
+                    Rule::Custom(path) => struct_checks.push(quote! {
+                        if let Err(err) = #path(self) {
+                            errors.push(key, err);
+                        }
+                    }),
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "only `custom` is supported on the struct",
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    let rename_all = match serde_name(&input.attrs, "rename_all")? {
+        Some(rule) => Some(RenameRule::parse(&rule)?),
+        None => None,
+    };
+
+    let mut field_checks = vec![];
+    for field in fields {
+        let mut rules = vec![];
+        for attr in &field.attrs {
+            if attr.path().is_ident("validate") {
+                rules.extend(parse_rules(attr)?);
+            }
+        }
+        if rules.is_empty() {
+            continue;
+        }
+
+        let name = field.ident.as_ref().unwrap();
+        let key = serde_name(&field.attrs, "rename")?.map(|lit| lit.value());
+        let key = key.unwrap_or_else(|| {
+            let name = name.to_string();
+            let name = name.strip_prefix("r#").unwrap_or(&name);
+            match &rename_all {
+                Some(rule) => rule.apply(name),
+                None => name.to_string(),
+            }
+        });
+        let checks = rules.iter().map(Rule::check);
+        let body = quote! {
+            let key = ::lolibaso::configs::validate::join_key(key, #key);
+            #(#checks)*
+        };
+        field_checks.push(match is_option(&field.ty) {
+            true => quote!(if let Some(value) = &self.#name { #body }),
+            false => quote!({ let value = &self.#name; #body }),
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::lolibaso::configs::ValidateConfig for #ident #ty_generics #where_clause {
+            fn validate_at(&self, key: &str, errors: &mut ::lolibaso::configs::ValidationErrors) {
+                #(#field_checks)*
+                #(#struct_checks)*
+            }
+        }
+    })
+}
+
+enum Rule {
+    Range {
+        min: Option<Box<syn::Expr>>,
+        max: Option<Box<syn::Expr>>,
+    },
+    NonEmpty,
+    Regex(syn::LitStr),
+    Url,
+    Custom(syn::Path),
+    Nested,
+}
+
+impl Rule {
+    fn check(&self) -> TokenStream {
+        match self {
+            Rule::Range { min, max } => {
+                let min = min.as_ref().map(|min| {
+                    quote! {
+                        if *value < #min {
+                            errors.push(&key, format!("must be at least {}", #min));
+                        }
+                    }
+                });
+                let max = max.as_ref().map(|max| {
+                    quote! {
+                        if *value > #max {
+                            errors.push(&key, format!("must be at most {}", #max));
+                        }
+                    }
+                });
+                quote!(#min #max)
+            }
+            Rule::NonEmpty => quote! {
+                if ::lolibaso::configs::validate::IsEmpty::is_empty(value) {
+                    errors.push(&key, "must not be empty");
+                }
+            },
+            // A block per pattern, so that several regex rules on one field
+            // each get their own `RE`. Wrapped in a macro that fails to
+            // compile without the `regex` feature of lolibaso.
+            Rule::Regex(pattern) => quote! {
+                ::lolibaso::__validate_regex! {{
+                    static RE: ::std::sync::OnceLock<::lolibaso::configs::validate::Regex> =
+                        ::std::sync::OnceLock::new();
+                    if !::lolibaso::configs::validate::matches_regex(&RE, #pattern, ::std::convert::AsRef::<str>::as_ref(value)) {
+                        errors.push(&key, format!("must match `{}`", #pattern));
+                    }
+                }}
+            },
+            Rule::Url => quote! {
+                ::lolibaso::__validate_url! {
+                    if let Err(err) = ::lolibaso::configs::validate::check_url(::std::convert::AsRef::<str>::as_ref(value)) {
+                        errors.push(&key, format!("must be a valid URL: {err}"));
+                    }
+                }
+            },
+            Rule::Custom(path) => quote! {
+                if let Err(err) = #path(value) {
+                    errors.push(&key, err);
+                }
+            },
+            Rule::Nested => quote! {
+                ::lolibaso::configs::ValidateConfig::validate_at(value, &key, errors);
+            },
+        }
+    }
+}
+
+fn parse_rules(attr: &syn::Attribute) -> syn::Result<Vec<Rule>> {
+    let mut rules = vec![];
+    attr.parse_nested_meta(|meta| {
+        let rule = if meta.path.is_ident("range") {
+            let (mut min, mut max) = (None, None);
+            meta.parse_nested_meta(|bound| {
+                if bound.path.is_ident("min") {
+                    min = Some(bound.value()?.parse()?);
+                } else if bound.path.is_ident("max") {
+                    max = Some(bound.value()?.parse()?);
+                } else {
+                    return Err(bound.error("expected `min` or `max`"));
+                }
+                Ok(())
+            })?;
+            Rule::Range { min, max }
+        } else if meta.path.is_ident("non_empty") {
+            Rule::NonEmpty
+        } else if meta.path.is_ident("regex") {
+            let pattern: syn::LitStr = meta.value()?.parse()?;
+            if let Err(err) = regex::Regex::new(&pattern.value()) {
+                return Err(syn::Error::new_spanned(&pattern, err));
+            }
+            Rule::Regex(pattern)
+        } else if meta.path.is_ident("url") {
+            Rule::Url
+        } else if meta.path.is_ident("custom") {
+            Rule::Custom(meta.value()?.parse()?)
+        } else if meta.path.is_ident("nested") {
+            Rule::Nested
+        } else {
+            return Err(meta.error("unknown validate rule"));
+        };
+        rules.push(rule);
+        Ok(())
+    })?;
+    Ok(rules)
+}
+
+/// The value of `#[serde(<name> = "...")]` or
+/// `#[serde(<name>(deserialize = "..."))]`, so that violations name the key as
+/// written in the config source.
+fn serde_name(attrs: &[syn::Attribute], name: &str) -> syn::Result<Option<syn::LitStr>> {
+    let mut value = None;
+    for attr in attrs {
+        if !attr.path().is_ident("serde") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(name) && meta.input.peek(syn::Token![=]) {
+                value = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident(name) {
+                meta.parse_nested_meta(|meta| {
+                    let lit: syn::LitStr = meta.value()?.parse()?;
+                    if meta.path.is_ident("deserialize") {
+                        value = Some(lit);
+                    }
+                    Ok(())
+                })?;
+            } else if !meta.input.is_empty() && !meta.input.peek(syn::Token![,]) {
+                // Skip the value of any other serde attribute.
+                if meta.input.peek(syn::Token![=]) {
+                    meta.value()?.parse::<syn::Expr>()?;
+                } else {
+                    let content;
+                    syn::parenthesized!(content in meta.input);
+                    content.parse::<TokenStream>()?;
+                }
+            }
+            Ok(())
+        })?;
+    }
+    Ok(value)
+}
+
+/// The field naming rules of `#[serde(rename_all = "...")]`.
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(rule: &syn::LitStr) -> syn::Result<Self> {
+        Ok(match rule.value().as_str() {
+            "lowercase" => RenameRule::Lower,
+            "UPPERCASE" => RenameRule::Upper,
+            "PascalCase" => RenameRule::Pascal,
+            "camelCase" => RenameRule::Camel,
+            "snake_case" => RenameRule::Snake,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnake,
+            "kebab-case" => RenameRule::Kebab,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebab,
+            _ => return Err(syn::Error::new_spanned(rule, "unknown rename_all rule")),
+        })
+    }
+
+    /// Renames a snake_case field the way serde does.
+    fn apply(&self, field: &str) -> String {
+        match self {
+            RenameRule::Lower | RenameRule::Snake => field.to_string(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for ch in field.chars() {
+                    if ch == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(ch.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(ch);
+                    }
+                }
+                pascal
+            }
+            RenameRule::Camel => {
+                let pascal = RenameRule::Pascal.apply(field);
+                let mut chars = pascal.chars();
+                match chars.next() {
+                    Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+                    None => pascal,
+                }
+            }
+            RenameRule::Kebab => field.replace('_', "-"),
+            RenameRule::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+fn is_option(ty: &syn::Type) -> bool {
+    let syn::Type::Path(path) = ty else {
+        return false;
+    };
+    path.path
+        .segments
+        .last()
+        .is_some_and(|seg| seg.ident == "Option" && !seg.arguments.is_empty())
+}
@@ -105,6 +105,7 @@ pub mod impl_tokio {
 
     use super::*;
 
+    #[derive(Default)]
     pub struct BroadcastChanBuilderTokio {}
 
     impl BroadcastChanBuilderTokio {
@@ -81,6 +81,7 @@ pub trait DuplexChanClientDyn {
 
     async fn receive(&mut self) -> Result<Option<Self::Event>, Lagged>;
 
+    #[allow(clippy::type_complexity)]
     fn split(
         self,
     ) -> (
@@ -101,6 +102,7 @@ pub trait DuplexChanServerDyn {
 
     async fn receive(&mut self) -> Option<Self::Command>;
 
+    #[allow(clippy::type_complexity)]
     fn split(
         self,
     ) -> (
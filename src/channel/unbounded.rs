@@ -49,6 +49,7 @@ where
 pub mod impl_tokio {
 
     use super::*;
+    #[derive(Default)]
     pub struct UnboundedChannelBuilderTokio {}
 
     impl UnboundedChannelBuilderTokio {
@@ -1,3 +1,79 @@
+use std::{
+    any::{Any, TypeId},
+    sync::Arc,
+};
+
+mod de;
+mod loader;
+mod reload;
+#[cfg(feature = "schema")]
+mod schema;
+mod secret;
+pub mod validate;
+
+pub use loader::{ConfigError, ConfigLoader};
+#[cfg(feature = "watch")]
+pub use reload::ConfigWatcher;
+pub use reload::ReloadableConfig;
+#[cfg(feature = "schema")]
+pub use schema::ConfigSchema;
+pub use secret::{Secret, with_secrets_exposed};
+pub use validate::{ValidateConfig, ValidationErrors, Violation, check_on_startup};
+
+/// Implemented by `#[derive(GetConfig)]` for every field of a config struct,
+/// and for every field reachable through `#[config(flatten)]` fields.
+///
+/// Fields marked `#[config(skip)]` are not exposed. Two sections of the same
+/// type are ambiguous and fail to compile.
+///
+/// A `#[config(flatten)]` field must name a struct deriving `GetConfig` in the
+/// same crate: the derive reaches its sections through a helper macro that is
+/// only exported `pub(crate)`.
 pub trait GetConfig<T> {
     fn get_config(&self) -> &T;
 }
+
+/// The type of the field at `INDEX`, used by `#[derive(GetConfig)]` to name
+/// flattened sections.
+#[doc(hidden)]
+pub trait ConfigField<const INDEX: usize> {
+    type Type;
+}
+
+/// A root configuration whose sections are looked up by type, implemented by
+/// `#[derive(GetConfig)]` on structs marked `#[config(root)]`.
+///
+/// Register it with
+/// [`ProviderContext::insert_config`](crate::provider::ProviderContext::insert_config)
+/// to resolve `#[provider(config)]` fields.
+pub trait ConfigRoot: Any + Send + Sync {
+    /// Returns the section of type `type_id`, or the root itself.
+    fn get_section(&self, type_id: TypeId) -> Option<&dyn Any>;
+}
+
+/// Looks up the sections of a `#[derive(GetConfig)]` struct by type, including
+/// flattened ones. Unlike [`ConfigRoot`] it does not require `Send + Sync`.
+#[doc(hidden)]
+pub trait ConfigSections: Any {
+    fn get_section(&self, type_id: TypeId) -> Option<&dyn Any>;
+}
+
+/// Supplies the root configuration of a
+/// [`ProviderContext`](crate::provider::ProviderContext), which may change
+/// between builds.
+pub trait ConfigSource: Send + Sync + 'static {
+    fn current_root(&self) -> Arc<dyn ConfigRoot>;
+}
+
+impl ConfigSource for Arc<dyn ConfigRoot> {
+    fn current_root(&self) -> Arc<dyn ConfigRoot> {
+        self.clone()
+    }
+}
+
+/// Renders `config` as pretty JSON, e.g. to log the effective config at
+/// startup. [`Secret`]s are redacted.
+pub fn dump<T: serde::Serialize>(config: &T) -> String {
+    serde_json::to_string_pretty(config)
+        .unwrap_or_else(|err| format!("<unserializable config: {err}>"))
+}
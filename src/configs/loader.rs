@@ -0,0 +1,494 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value};
+use serde_path_to_error::Segment;
+
+use super::{ValidateConfig, de::Lenient, secret::with_secrets_exposed};
+
+/// Builds a config struct from layered sources. Later layers override
+/// earlier ones, key by key:
+///
+/// 1. defaults, in the order added
+/// 2. files, in the order added
+/// 3. the profile overlay of each file, e.g. `app.dev.toml` for `app.toml`
+/// 4. environment variables
+///
+/// ```ignore
+/// let config: AppConfig = ConfigLoader::new()
+///     .defaults(AppConfig::default())
+///     .file("config/app.toml")
+///     .profile("dev")
+///     .env_prefix("APP")
+///     .load()?;
+/// ```
+///
+/// Errors are [`ConfigError`]s naming the source and key path.
+pub struct ConfigLoader {
+    layers: Vec<Layer>,
+    profile: Option<String>,
+    env: Option<EnvSource>,
+}
+
+enum Layer {
+    Defaults(Result<Value, String>),
+    File { path: PathBuf, required: bool },
+}
+
+pub(super) struct EnvSource {
+    pub(super) prefix: String,
+    pub(super) separator: String,
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        ConfigLoader {
+            layers: vec![],
+            profile: None,
+            env: None,
+        }
+    }
+
+    /// Adds `defaults` as a layer. [`Secret`](super::Secret)s keep their
+    /// values.
+    pub fn defaults<D: Serialize>(mut self, defaults: D) -> Self {
+        let value =
+            with_secrets_exposed(|| serde_json::to_value(defaults)).map_err(|err| err.to_string());
+        self.layers.push(Layer::Defaults(value));
+        self
+    }
+
+    /// Adds a TOML, JSON or YAML file, chosen by extension. The file must
+    /// exist.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.layers.push(Layer::File {
+            path: path.into(),
+            required: true,
+        });
+        self
+    }
+
+    /// Like [`ConfigLoader::file`], but skipped if the file does not exist.
+    pub fn optional_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.layers.push(Layer::File {
+            path: path.into(),
+            required: false,
+        });
+        self
+    }
+
+    /// Overlays `<stem>.<profile>.<ext>` on every file, if it exists.
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// Maps `<PREFIX>_<KEY>` environment variables onto config keys. Nested
+    /// keys are separated by `__`, so `APP_DB__MAX_CONNS` sets `db.max_conns`.
+    pub fn env_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.env = Some(EnvSource::new(prefix));
+        self
+    }
+
+    /// Replaces the `__` separator between nested keys of environment
+    /// variables. Has no effect without [`ConfigLoader::env_prefix`].
+    pub fn env_separator(mut self, separator: impl Into<String>) -> Self {
+        if let Some(env) = &mut self.env {
+            env.separator = separator.into();
+        }
+        self
+    }
+
+    /// The files this loader reads, including profile overlays.
+    #[cfg_attr(not(feature = "watch"), allow(dead_code))]
+    pub(super) fn file_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![];
+        for layer in &self.layers {
+            if let Layer::File { path, .. } = layer {
+                paths.push(path.clone());
+                if let Some(profile) = &self.profile {
+                    paths.push(profile_path(path, profile));
+                }
+            }
+        }
+        paths
+    }
+
+    pub fn load<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        self.load_with_vars(std::env::vars())
+    }
+
+    /// Loads `T` and checks it, failing with every
+    /// [`ValidationErrors`](super::ValidationErrors) violation at once.
+    pub fn load_validated<T>(&self) -> anyhow::Result<T>
+    where
+        T: DeserializeOwned + ValidateConfig,
+    {
+        let config = self.load::<T>()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn load_with_vars<T: DeserializeOwned>(
+        &self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> anyhow::Result<T> {
+        let mut tree = SourceTree::default();
+
+        for layer in &self.layers {
+            match layer {
+                Layer::Defaults(Ok(value)) => tree.merge(value.clone(), "defaults"),
+                Layer::Defaults(Err(err)) => {
+                    return Err(ConfigError::new(err.clone()).source("defaults").into());
+                }
+                Layer::File { .. } => {}
+            }
+        }
+
+        for layer in &self.layers {
+            if let Layer::File { path, required } = layer
+                && let Some(value) = read_file(path, *required)?
+            {
+                tree.merge(value, &path.display().to_string());
+            }
+        }
+
+        if let Some(profile) = &self.profile {
+            for layer in &self.layers {
+                if let Layer::File { path, .. } = layer {
+                    let path = profile_path(path, profile);
+                    if let Some(value) = read_file(&path, false)? {
+                        tree.merge(value, &path.display().to_string());
+                    }
+                }
+            }
+        }
+
+        if let Some(env) = &self.env {
+            let mut vars: Vec<_> = vars
+                .into_iter()
+                .filter_map(|(name, value)| Some((env.key_path(&name)?, name, value)))
+                .collect();
+            vars.sort();
+            for (path, name, value) in vars {
+                tree.set(&path, Value::String(value), &format!("env {name}"));
+            }
+        }
+
+        tree.deserialize()
+    }
+}
+
+impl EnvSource {
+    pub(super) fn new(prefix: impl Into<String>) -> Self {
+        EnvSource {
+            prefix: prefix.into(),
+            separator: "__".into(),
+        }
+    }
+
+    /// The variable that sets `path`, the inverse of [`EnvSource::key_path`].
+    #[cfg(feature = "schema")]
+    pub(super) fn var_name(&self, path: &[String]) -> String {
+        let key = path.join(&self.separator).to_uppercase();
+        format!("{}_{key}", self.prefix)
+    }
+
+    fn key_path(&self, name: &str) -> Option<Vec<String>> {
+        let key = name.strip_prefix(&self.prefix)?.strip_prefix('_')?;
+        let path: Vec<_> = key
+            .split(self.separator.as_str())
+            .map(str::to_lowercase)
+            .collect();
+        if path.iter().any(String::is_empty) {
+            return None;
+        }
+        Some(path)
+    }
+}
+
+fn profile_path(path: &Path, profile: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.{profile}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{profile}"),
+    };
+    path.with_file_name(name)
+}
+
+fn read_file(path: &Path, required: bool) -> anyhow::Result<Option<Value>> {
+    let source = path.display().to_string();
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => return Ok(None),
+        Err(err) => return Err(ConfigError::new(err.to_string()).source(source).into()),
+    };
+
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let parsed = match ext.as_str() {
+        "json" => serde_json::from_str(&text).map_err(|err| err.to_string()),
+        #[cfg(feature = "toml")]
+        "toml" => toml::from_str(&text).map_err(|err| err.to_string()),
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => serde_yaml::from_str(&text).map_err(|err| err.to_string()),
+        _ => Err(format!("unsupported config format `{ext}`")),
+    };
+    match parsed {
+        Ok(value) => Ok(Some(value)),
+        Err(err) => Err(ConfigError::new(err).source(source).into()),
+    }
+}
+
+/// A merged config tree that remembers which source set each key.
+#[derive(Default)]
+struct SourceTree {
+    root: Value,
+    origins: HashMap<Vec<String>, String>,
+}
+
+impl SourceTree {
+    fn merge(&mut self, value: Value, source: &str) {
+        let mut path = vec![];
+        merge_value(&mut self.root, value, &mut path, source, &mut self.origins);
+    }
+
+    fn set(&mut self, path: &[String], value: Value, source: &str) {
+        let mut nested = value;
+        for key in path.iter().rev() {
+            nested = Value::Object(Map::from_iter([(key.clone(), nested)]));
+        }
+        self.merge(nested, source);
+    }
+
+    fn deserialize<T: DeserializeOwned>(self) -> anyhow::Result<T> {
+        serde_path_to_error::deserialize(Lenient(self.root)).map_err(|err| {
+            let mut key = vec![];
+            for segment in err.path().iter() {
+                match segment {
+                    Segment::Map { key: k } => key.push(k.clone()),
+                    _ => break,
+                }
+            }
+            let source = (0..=key.len())
+                .rev()
+                .find_map(|len| self.origins.get(&key[..len]));
+
+            let mut config_err = ConfigError::new(err.inner().to_string());
+            config_err.key = Some(err.path().to_string());
+            config_err.source = source.cloned();
+            config_err.into()
+        })
+    }
+}
+
+fn merge_value(
+    target: &mut Value,
+    value: Value,
+    path: &mut Vec<String>,
+    source: &str,
+    origins: &mut HashMap<Vec<String>, String>,
+) {
+    match (target, value) {
+        (Value::Object(target), Value::Object(map)) => {
+            origins
+                .entry(path.clone())
+                .or_insert_with(|| source.to_string());
+            for (key, value) in map {
+                path.push(key.clone());
+                match target.get_mut(&key) {
+                    Some(existing) => merge_value(existing, value, path, source, origins),
+                    None => {
+                        record_origins(&value, path, source, origins);
+                        target.insert(key, value);
+                    }
+                }
+                path.pop();
+            }
+        }
+        (target, value) => {
+            origins.retain(|key, _| !key.starts_with(path));
+            record_origins(&value, path, source, origins);
+            *target = value;
+        }
+    }
+}
+
+fn record_origins(
+    value: &Value,
+    path: &mut Vec<String>,
+    source: &str,
+    origins: &mut HashMap<Vec<String>, String>,
+) {
+    origins.insert(path.clone(), source.to_string());
+    if let Value::Object(map) = value {
+        for (key, value) in map {
+            path.push(key.clone());
+            record_origins(value, path, source, origins);
+            path.pop();
+        }
+    }
+}
+
+/// A config source that could not be read, parsed or deserialized.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub message: String,
+    /// The key path, e.g. `db.hosts[0]`. `.` is the root.
+    pub key: Option<String>,
+    /// The source that last set the key, e.g. `config/app.toml` or
+    /// `env APP_DB__PORT`.
+    pub source: Option<String>,
+}
+
+impl ConfigError {
+    fn new(message: impl Into<String>) -> Self {
+        ConfigError {
+            message: message.into(),
+            key: None,
+            source: None,
+        }
+    }
+
+    fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ConfigLoader::load: {}", self.message)?;
+        if let Some(key) = &self.key {
+            write!(f, ". key = {key}")?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, ". source = {source}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    struct AppConfig {
+        name: String,
+        db: DbConfig,
+        hosts: Vec<String>,
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    #[serde(default)]
+    struct DbConfig {
+        url: String,
+        port: u16,
+        pool: Option<u32>,
+    }
+
+    fn write(dir: &TempDir, name: &str, content: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn t_layers_override_in_order() {
+        let dir = TempDir::new().unwrap();
+        let base = write(
+            &dir,
+            "layers.json",
+            r#"{"name": "app", "db": {"url": "pg://base", "port": 1}}"#,
+        );
+        write(&dir, "layers.dev.json", r#"{"db": {"url": "pg://dev"}}"#);
+
+        let config: AppConfig = ConfigLoader::new()
+            .defaults(AppConfig {
+                hosts: vec!["default".into()],
+                ..Default::default()
+            })
+            .file(&base)
+            .profile("dev")
+            .env_prefix("APP")
+            .load_with_vars(vars(&[
+                ("APP_DB__PORT", "5432"),
+                ("APP_HOSTS", r#"["a", "b"]"#),
+                ("OTHER_NAME", "ignored"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.name, "app");
+        assert_eq!(config.db.url, "pg://dev");
+        assert_eq!(config.db.port, 5432);
+        assert_eq!(config.db.pool, None);
+        assert_eq!(config.hosts, ["a", "b"]);
+    }
+
+    #[test]
+    fn t_files_override_defaults() {
+        let dir = TempDir::new().unwrap();
+        let base = write(&dir, "order.json", r#"{"name": "file"}"#);
+
+        let config: AppConfig = ConfigLoader::new()
+            .file(&base)
+            .defaults(AppConfig {
+                name: "defaults".into(),
+                hosts: vec!["default".into()],
+                ..Default::default()
+            })
+            .load_with_vars(vec![])
+            .unwrap();
+        assert_eq!(config.name, "file");
+        assert_eq!(config.hosts, ["default"]);
+    }
+
+    #[test]
+    fn t_error_names_source_and_key() {
+        let dir = TempDir::new().unwrap();
+        let base = write(&dir, "errors.json", r#"{"db": {"port": 1}}"#);
+        let loader = ConfigLoader::new().file(&base).env_prefix("APP");
+
+        let err = loader
+            .load_with_vars::<AppConfig>(vars(&[("APP_DB__PORT", "high")]))
+            .unwrap_err();
+        let err = err.downcast::<ConfigError>().unwrap();
+        assert_eq!(err.key.as_deref(), Some("db.port"));
+        assert_eq!(err.source.as_deref(), Some("env APP_DB__PORT"));
+
+        let broken = write(&dir, "broken.json", "{");
+        let err = ConfigLoader::new()
+            .file(&broken)
+            .load::<AppConfig>()
+            .unwrap_err()
+            .downcast::<ConfigError>()
+            .unwrap();
+        assert_eq!(err.source, Some(broken.display().to_string()));
+    }
+}
@@ -0,0 +1,261 @@
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use serde::de::DeserializeOwned;
+
+use super::{ConfigLoader, ConfigRoot, ConfigSource, GetConfig};
+use crate::channel::broadcast::{BroadcastChanBuilder, BroadcastSender};
+
+type Validator<T> = Box<dyn Fn(&T) -> anyhow::Result<()> + Send + Sync>;
+
+/// A config that can be reloaded at runtime, e.g. for rate limits, feature
+/// toggles or log levels.
+///
+/// Readers get the current snapshot with [`ReloadableConfig::current`] or
+/// [`ReloadableConfig::section`]. Every successful reload is broadcast to
+/// subscribers. A reload that fails to load or validate keeps the previous
+/// snapshot.
+///
+/// Registered with
+/// [`ProviderContext::insert_config_source`](crate::provider::ProviderContext::insert_config_source),
+/// `#[provider(config)]` fields see the snapshot current at build time.
+pub struct ReloadableConfig<T, S> {
+    inner: Arc<Inner<T, S>>,
+}
+
+struct Inner<T, S> {
+    loader: ConfigLoader,
+    validate: Option<Validator<T>>,
+    current: RwLock<Arc<T>>,
+    /// Held for a whole reload, so snapshots are installed and broadcast in
+    /// the order they were loaded.
+    reloading: Mutex<()>,
+    sender: S,
+}
+
+impl<T, S> Clone for ReloadableConfig<T, S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, S> ReloadableConfig<T, S>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+    S: BroadcastSender<Arc<T>>,
+{
+    pub fn load<B>(loader: ConfigLoader, chan: &B) -> anyhow::Result<Self>
+    where
+        B: BroadcastChanBuilder<Arc<T>, Sender = S>,
+    {
+        Self::build(loader, chan, None)
+    }
+
+    /// Like [`ReloadableConfig::load`], but every loaded config must also
+    /// pass `validate`.
+    pub fn load_validated<B, F>(loader: ConfigLoader, chan: &B, validate: F) -> anyhow::Result<Self>
+    where
+        B: BroadcastChanBuilder<Arc<T>, Sender = S>,
+        F: Fn(&T) -> anyhow::Result<()> + Send + Sync + 'static,
+    {
+        Self::build(loader, chan, Some(Box::new(validate)))
+    }
+
+    fn build<B>(
+        loader: ConfigLoader,
+        chan: &B,
+        validate: Option<Validator<T>>,
+    ) -> anyhow::Result<Self>
+    where
+        B: BroadcastChanBuilder<Arc<T>, Sender = S>,
+    {
+        let config = load_validated(&loader, validate.as_ref())?;
+        let (sender, _) = chan.chan_with_capacity(16);
+        Ok(ReloadableConfig {
+            inner: Arc::new(Inner {
+                loader,
+                validate,
+                current: RwLock::new(Arc::new(config)),
+                reloading: Mutex::new(()),
+                sender,
+            }),
+        })
+    }
+
+    pub fn current(&self) -> Arc<T> {
+        self.inner.current.read().clone()
+    }
+
+    /// Clones section `U` out of the current snapshot.
+    pub fn section<U>(&self) -> U
+    where
+        T: GetConfig<U>,
+        U: Clone,
+    {
+        self.inner.current.read().get_config().clone()
+    }
+
+    /// Receives every snapshot installed after subscribing.
+    pub fn subscribe(&self) -> S::Receiver {
+        self.inner.sender.subscribe()
+    }
+
+    /// Loads and validates the config again, then installs and broadcasts
+    /// it. On failure the current snapshot is kept.
+    pub fn reload(&self) -> anyhow::Result<Arc<T>> {
+        let _reloading = self.inner.reloading.lock();
+        let config = Arc::new(load_validated(
+            &self.inner.loader,
+            self.inner.validate.as_ref(),
+        )?);
+        *self.inner.current.write() = config.clone();
+        // No subscribers is not an error.
+        let _ = self.inner.sender.send(config.clone());
+        Ok(config)
+    }
+
+    /// Reloads whenever one of the loader's files, or its profile overlay,
+    /// changes. Watching stops when the returned watcher is dropped.
+    #[cfg(feature = "watch")]
+    pub fn watch(&self) -> anyhow::Result<ConfigWatcher> {
+        use std::{collections::HashSet, sync::mpsc, time::Duration};
+
+        use notify::Watcher;
+
+        let paths = self.inner.loader.file_paths();
+        let dirs: HashSet<_> = paths
+            .iter()
+            .map(|p| match p.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => ".".into(),
+            })
+            .collect();
+
+        let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        for dir in &dirs {
+            watcher.watch(dir, notify::RecursiveMode::NonRecursive)?;
+        }
+
+        let file_names: HashSet<_> = paths
+            .iter()
+            .filter_map(|p| p.file_name().map(ToOwned::to_owned))
+            .collect();
+        let is_config = move |event: &notify::Event| {
+            event
+                .paths
+                .iter()
+                .any(|p| p.file_name().is_some_and(|n| file_names.contains(n)))
+        };
+
+        let this = self.clone();
+        std::thread::spawn(move || {
+            while let Ok(event) = rx.recv() {
+                if !event.is_ok_and(|e| is_config(&e)) {
+                    continue;
+                }
+                // Editors touch a file several times per save.
+                while rx.recv_timeout(Duration::from_millis(100)).is_ok() {}
+                if let Err(err) = this.reload() {
+                    tracing::warn!("config reload failed: {err:#}");
+                }
+            }
+        });
+
+        Ok(ConfigWatcher { _watcher: watcher })
+    }
+}
+
+fn load_validated<T: DeserializeOwned>(
+    loader: &ConfigLoader,
+    validate: Option<&Validator<T>>,
+) -> anyhow::Result<T> {
+    let config = loader.load::<T>()?;
+    if let Some(validate) = validate {
+        validate(&config)?;
+    }
+    Ok(config)
+}
+
+impl<T, S> ConfigSource for ReloadableConfig<T, S>
+where
+    T: ConfigRoot,
+    S: Send + Sync + 'static,
+{
+    fn current_root(&self) -> Arc<dyn ConfigRoot> {
+        self.inner.current.read().clone()
+    }
+}
+
+/// Returned by [`ReloadableConfig::watch`].
+#[cfg(feature = "watch")]
+pub struct ConfigWatcher {
+    _watcher: notify::RecommendedWatcher,
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::channel::broadcast::{BroadcastReceiver, impl_tokio::BroadcastChanBuilderTokio};
+
+    #[derive(Debug, Deserialize)]
+    struct Limits {
+        rate: u32,
+    }
+
+    #[tokio::test]
+    async fn t_reload_and_notify() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("limits.json");
+        std::fs::write(&path, r#"{"rate": 10}"#).unwrap();
+
+        let config = ReloadableConfig::load_validated(
+            ConfigLoader::new().file(&path),
+            &BroadcastChanBuilderTokio::new(),
+            |limits: &Limits| {
+                anyhow::ensure!(limits.rate > 0, "rate must be positive");
+                Ok(())
+            },
+        )
+        .unwrap();
+        let mut changes = config.subscribe();
+        assert_eq!(config.current().rate, 10);
+
+        std::fs::write(&path, r#"{"rate": 20}"#).unwrap();
+        config.reload().unwrap();
+        assert_eq!(changes.recv().await.unwrap().unwrap().rate, 20);
+
+        std::fs::write(&path, r#"{"rate": 0}"#).unwrap();
+        assert!(config.reload().is_err());
+        assert_eq!(config.current().rate, 20);
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test]
+    async fn t_watch_reloads_on_change() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("limits.json");
+        std::fs::write(&path, r#"{"rate": 10}"#).unwrap();
+
+        let config = ReloadableConfig::<Limits, _>::load(
+            ConfigLoader::new().file(&path),
+            &BroadcastChanBuilderTokio::new(),
+        )
+        .unwrap();
+        let mut changes = config.subscribe();
+        let _watcher = config.watch().unwrap();
+
+        std::fs::write(&path, r#"{"rate": 30}"#).unwrap();
+        let limits = tokio::time::timeout(std::time::Duration::from_secs(10), changes.recv())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(limits.rate, 30);
+        assert_eq!(config.current().rate, 30);
+    }
+}
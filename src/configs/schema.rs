@@ -0,0 +1,329 @@
+use std::fmt::Write;
+
+use schemars::{JsonSchema, SchemaGenerator};
+use serde_json::{Map, Value};
+
+use super::loader::EnvSource;
+
+/// The JSON Schema and the Markdown reference of a config struct deriving
+/// `schemars::JsonSchema`, e.g. to generate them from a build script and
+/// check them into a deploy repo.
+///
+/// ```ignore
+/// #[derive(Deserialize, GetConfig, JsonSchema)]
+/// #[config(root)]
+/// #[schemars(crate = "lolibaso::schemars")]
+/// struct AppConfig {
+///     /// The database to connect to.
+///     db: DbConfig,
+/// }
+///
+/// let schema = ConfigSchema::of::<AppConfig>().env_prefix("APP");
+/// std::fs::write("config.schema.json", schema.to_json_string())?;
+/// std::fs::write("CONFIG.md", schema.to_markdown())?;
+/// ```
+///
+/// Doc comments become descriptions and `#[serde(default)]` values become
+/// defaults. [`Secret`](super::Secret) defaults are redacted.
+pub struct ConfigSchema {
+    schema: Value,
+    env: Option<EnvSource>,
+}
+
+impl ConfigSchema {
+    pub fn of<T: JsonSchema>() -> Self {
+        let schema = SchemaGenerator::default().into_root_schema_for::<T>();
+        ConfigSchema {
+            schema: schema.to_value(),
+            env: None,
+        }
+    }
+
+    /// Lists the environment variable of every key in the Markdown
+    /// reference, as read by
+    /// [`ConfigLoader::env_prefix`](super::ConfigLoader::env_prefix).
+    pub fn env_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.env = Some(EnvSource::new(prefix));
+        self
+    }
+
+    /// See [`ConfigLoader::env_separator`](super::ConfigLoader::env_separator).
+    pub fn env_separator(mut self, separator: impl Into<String>) -> Self {
+        if let Some(env) = &mut self.env {
+            env.separator = separator.into();
+        }
+        self
+    }
+
+    pub fn to_json(&self) -> &Value {
+        &self.schema
+    }
+
+    pub fn to_json_string(&self) -> String {
+        let mut json = serde_json::to_string_pretty(&self.schema).unwrap_or_default();
+        json.push('\n');
+        json
+    }
+
+    /// A Markdown table of every key: type, whether it is required, default,
+    /// environment variable and description.
+    pub fn to_markdown(&self) -> String {
+        let mut keys = vec![];
+        let defs = self.schema.get("$defs").and_then(Value::as_object);
+        walk(&self.schema, defs, &mut vec![], None, true, &mut keys);
+
+        let mut out = String::new();
+        if let Some(title) = self.schema.get("title").and_then(Value::as_str) {
+            let _ = writeln!(out, "# {title}\n");
+        }
+        if let Some(description) = self.schema.get("description").and_then(Value::as_str) {
+            let _ = writeln!(out, "{description}\n");
+        }
+
+        let env_col = if self.env.is_some() { " Env |" } else { "" };
+        let env_sep = if self.env.is_some() { "-----|" } else { "" };
+        let _ = writeln!(
+            out,
+            "| Key | Type | Required | Default |{env_col} Description |"
+        );
+        let _ = writeln!(
+            out,
+            "|-----|------|----------|---------|{env_sep}-------------|"
+        );
+        for key in keys {
+            let env = match &self.env {
+                Some(env) => format!(" `{}` |", env.var_name(&key.path)),
+                None => String::new(),
+            };
+            let default = match &key.default {
+                Some(default) => format!("`{}`", cell(&default.to_string())),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                out,
+                "| `{}` | {} | {} | {} |{env} {} |",
+                key.path.join("."),
+                cell(&key.ty),
+                if key.required { "yes" } else { "no" },
+                default,
+                cell(&key.description),
+            );
+        }
+        out
+    }
+}
+
+struct Key {
+    path: Vec<String>,
+    ty: String,
+    required: bool,
+    default: Option<Value>,
+    description: String,
+}
+
+/// Collects the leaf keys under `schema`. Defaults of a parent object are
+/// passed down, since `#[serde(default)]` on a struct only sets the default
+/// of the whole section.
+fn walk(
+    schema: &Value,
+    defs: Option<&Map<String, Value>>,
+    path: &mut Vec<String>,
+    default: Option<&Value>,
+    required: bool,
+    keys: &mut Vec<Key>,
+) {
+    let (resolved, nullable) = resolve(schema, defs);
+    let default = schema.get("default").or(default);
+    let properties = resolved.get("properties").and_then(Value::as_object);
+
+    match properties {
+        Some(properties) if path.len() < MAX_DEPTH => {
+            let required_keys = resolved.get("required").and_then(Value::as_array);
+            for (name, property) in properties {
+                let property_required =
+                    required && required_keys.is_some_and(|r| r.iter().any(|k| k == name.as_str()));
+                path.push(name.clone());
+                walk(
+                    property,
+                    defs,
+                    path,
+                    default.and_then(|d| d.get(name)),
+                    property_required,
+                    keys,
+                );
+                path.pop();
+            }
+        }
+        _ if path.is_empty() => {}
+        _ => keys.push(Key {
+            path: path.clone(),
+            ty: type_name(schema, defs),
+            required: required && !nullable && default.is_none(),
+            default: default.cloned(),
+            description: description(schema, resolved),
+        }),
+    }
+}
+
+/// Recursive configs stop here instead of overflowing the stack.
+const MAX_DEPTH: usize = 16;
+
+/// Follows `$ref`s into `$defs` and unwraps `Option`s. Returns the resolved
+/// schema and whether `null` is accepted.
+fn resolve<'a>(schema: &'a Value, defs: Option<&'a Map<String, Value>>) -> (&'a Value, bool) {
+    if let Some(target) = schema
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|r| r.strip_prefix("#/$defs/"))
+        .and_then(|name| defs?.get(name))
+    {
+        return resolve(target, defs);
+    }
+    for key in ["anyOf", "oneOf"] {
+        if let Some([a, b]) = schema.get(key).and_then(Value::as_array).map(Vec::as_slice) {
+            if is_null(b) {
+                return (resolve(a, defs).0, true);
+            }
+            if is_null(a) {
+                return (resolve(b, defs).0, true);
+            }
+        }
+    }
+    let nullable = match schema.get("type") {
+        Some(Value::Array(types)) => types.iter().any(|t| t == "null"),
+        _ => false,
+    };
+    (schema, nullable)
+}
+
+fn is_null(schema: &Value) -> bool {
+    schema.get("type").is_some_and(|t| t == "null")
+}
+
+fn type_name(schema: &Value, defs: Option<&Map<String, Value>>) -> String {
+    let (resolved, _) = resolve(schema, defs);
+
+    let mut name = if let Some(values) = enum_values(resolved) {
+        format!("one of {values}")
+    } else {
+        let types: Vec<_> = match resolved.get("type") {
+            Some(Value::String(ty)) => vec![ty.as_str()],
+            Some(Value::Array(types)) => types
+                .iter()
+                .filter_map(Value::as_str)
+                .filter(|ty| *ty != "null")
+                .collect(),
+            _ => vec![],
+        };
+        match types.as_slice() {
+            ["array"] => match resolved.get("items") {
+                Some(items) => format!("array of {}", type_name(items, defs)),
+                None => "array".into(),
+            },
+            ["object"] => match resolved.get("additionalProperties") {
+                Some(values @ Value::Object(_)) => format!("map of {}", type_name(values, defs)),
+                _ => "object".into(),
+            },
+            [] => "any".into(),
+            types => types.join(" or "),
+        }
+    };
+    if let Some(format) = resolved.get("format").and_then(Value::as_str) {
+        let _ = write!(name, " ({format})");
+    }
+    if [schema, resolved]
+        .iter()
+        .any(|s| s.get("writeOnly") == Some(&Value::Bool(true)))
+    {
+        name.push_str(", secret");
+    }
+    name
+}
+
+/// The values of a unit enum, written as `enum` or as a `oneOf` of `const`s
+/// when its variants have doc comments.
+fn enum_values(schema: &Value) -> Option<String> {
+    let values: Vec<_> = match (schema.get("enum"), schema.get("oneOf")) {
+        (Some(Value::Array(values)), _) => values.iter().collect(),
+        (_, Some(Value::Array(variants))) => variants
+            .iter()
+            .map(|v| v.get("const"))
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+    let values: Vec<_> = values.iter().map(|v| format!("`{v}`")).collect();
+    Some(values.join(", "))
+}
+
+fn description(schema: &Value, resolved: &Value) -> String {
+    schema
+        .get("description")
+        .or_else(|| resolved.get("description"))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Escapes a table cell.
+fn cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::configs::Secret;
+
+    /// Settings of the app.
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct AppConfig {
+        /// The database to connect to.
+        db: DbConfig,
+        log_level: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    #[serde(default)]
+    struct DbConfig {
+        /// Port of the
+        /// database.
+        port: u16,
+        password: Secret<String>,
+    }
+
+    impl Default for DbConfig {
+        fn default() -> Self {
+            DbConfig {
+                port: 5432,
+                password: Secret::new("postgres".into()),
+            }
+        }
+    }
+
+    #[test]
+    fn t_markdown_reference() {
+        let schema = ConfigSchema::of::<AppConfig>().env_prefix("APP");
+        assert_eq!(
+            schema.to_json()["$defs"]["DbConfig"]["properties"]["port"]["default"],
+            5432
+        );
+        assert_eq!(
+            schema.to_markdown(),
+            "# AppConfig\n\
+             \n\
+             Settings of the app.\n\
+             \n\
+             | Key | Type | Required | Default | Env | Description |\n\
+             |-----|------|----------|---------|-----|-------------|\n\
+             | `db.password` | string, secret | no | `\"[REDACTED]\"` | `APP_DB__PASSWORD` |  |\n\
+             | `db.port` | integer (uint16) | no | `5432` | `APP_DB__PORT` | Port of the database. |\n\
+             | `log_level` | string | no |  | `APP_LOG_LEVEL` |  |\n"
+        );
+    }
+}
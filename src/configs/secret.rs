@@ -0,0 +1,145 @@
+use std::{cell::Cell, fmt};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+use super::validate::IsEmpty;
+
+const REDACTED: &str = "[REDACTED]";
+
+thread_local! {
+    static EXPOSE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A config value such as a password or an API key.
+///
+/// Deserializes like `T`, but is redacted by `Debug`, `Display` and
+/// `Serialize`, so it does not leak through logs or config dumps. The value
+/// is zeroized on drop; buffers it was parsed from are not.
+#[derive(Clone, Default)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({REDACTED})")
+    }
+}
+
+impl<T: Zeroize> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Secret<T>
+where
+    T: Zeroize + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+/// Serializes as `"[REDACTED]"`, except inside [`with_secrets_exposed`].
+impl<T> Serialize for Secret<T>
+where
+    T: Zeroize + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if EXPOSE.get() {
+            self.0.serialize(serializer)
+        } else {
+            serializer.serialize_str(REDACTED)
+        }
+    }
+}
+
+impl<T: Zeroize + IsEmpty> IsEmpty for Secret<T> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The schema of `T`, marked `writeOnly`.
+#[cfg(feature = "schema")]
+impl<T: Zeroize + schemars::JsonSchema> schemars::JsonSchema for Secret<T> {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        format!("Secret_{}", T::schema_name()).into()
+    }
+
+    fn json_schema(generator: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        let mut schema = generator.subschema_for::<T>();
+        schema.insert("writeOnly".into(), true.into());
+        schema
+    }
+}
+
+/// Runs `f` with [`Secret`]s serializing their values on this thread, e.g.
+/// to pass a config as [`ConfigLoader::defaults`](super::ConfigLoader::defaults).
+pub fn with_secrets_exposed<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset(bool);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            EXPOSE.set(self.0);
+        }
+    }
+
+    let _reset = Reset(EXPOSE.replace(true));
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::configs::ConfigLoader;
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct DbConfig {
+        user: String,
+        password: Secret<String>,
+    }
+
+    #[test]
+    fn t_secret_redacted() {
+        let config: DbConfig =
+            serde_json::from_str(r#"{"user": "app", "password": "hunter2"}"#).unwrap();
+        assert_eq!(config.password.expose(), "hunter2");
+        assert_eq!(config.password.to_string(), "[REDACTED]");
+        assert!(!format!("{config:?}").contains("hunter2"));
+        assert_eq!(
+            serde_json::to_string(&config).unwrap(),
+            r#"{"user":"app","password":"[REDACTED]"}"#
+        );
+
+        let config: DbConfig = ConfigLoader::new().defaults(config).load().unwrap();
+        assert_eq!(config.password.expose(), "hunter2");
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+};
+
+/// Checks a loaded config, implemented by `#[derive(ValidateConfig)]`.
+///
+/// ```ignore
+/// #[derive(Deserialize, ValidateConfig)]
+/// #[validate(custom = check_pool)]
+/// struct DbConfig {
+///     #[validate(url)]
+///     url: String,
+///     #[validate(range(min = 1, max = 65535))]
+///     port: u16,
+///     #[validate(non_empty, regex = "^[a-z_]+$")]
+///     schema: String,
+///     #[validate(nested)]
+///     pool: PoolConfig,
+/// }
+/// ```
+///
+/// `Option` fields are checked only when set. Custom checks are functions
+/// taking the field, or the struct, and returning `Result<(), impl Display>`.
+/// Violation keys follow `#[serde(rename)]` and `#[serde(rename_all)]`.
+pub trait ValidateConfig {
+    /// Collects the violations of this config into `errors`, with keys
+    /// prefixed by `key`. The root key is empty.
+    fn validate_at(&self, key: &str, errors: &mut ValidationErrors);
+
+    /// Returns every violation at once.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        self.validate_at("", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Startup hook: validates `config` and logs every violation. Returning the
+/// error from `main`, or from an init function, refuses to boot.
+pub fn check_on_startup<T: ValidateConfig>(config: &T) -> anyhow::Result<()> {
+    config.validate().map_err(|errors| {
+        for violation in &errors.violations {
+            tracing::error!(key = violation.key, "invalid config: {}", violation.message);
+        }
+        anyhow::Error::new(errors)
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationErrors {
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The key path, e.g. `db.port`. `.` is the root.
+    pub key: String,
+    pub message: String,
+}
+
+impl ValidationErrors {
+    pub fn push(&mut self, key: impl Into<String>, message: impl fmt::Display) {
+        let key = key.into();
+        self.violations.push(Violation {
+            key: if key.is_empty() { ".".into() } else { key },
+            message: message.to_string(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} config violation(s)", self.violations.len())?;
+        for violation in &self.violations {
+            write!(f, "; {}: {}", violation.key, violation.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+#[doc(hidden)]
+pub fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+/// Values checked by `#[validate(non_empty)]`.
+pub trait IsEmpty {
+    fn is_empty(&self) -> bool;
+}
+
+impl IsEmpty for str {
+    fn is_empty(&self) -> bool {
+        str::is_empty(self)
+    }
+}
+
+impl IsEmpty for String {
+    fn is_empty(&self) -> bool {
+        String::is_empty(self)
+    }
+}
+
+impl IsEmpty for PathBuf {
+    fn is_empty(&self) -> bool {
+        self.as_os_str().is_empty()
+    }
+}
+
+impl<T> IsEmpty for Vec<T> {
+    fn is_empty(&self) -> bool {
+        Vec::is_empty(self)
+    }
+}
+
+impl<K, V, S> IsEmpty for HashMap<K, V, S> {
+    fn is_empty(&self) -> bool {
+        HashMap::is_empty(self)
+    }
+}
+
+impl<T, S> IsEmpty for HashSet<T, S> {
+    fn is_empty(&self) -> bool {
+        HashSet::is_empty(self)
+    }
+}
+
+impl<K, V> IsEmpty for BTreeMap<K, V> {
+    fn is_empty(&self) -> bool {
+        BTreeMap::is_empty(self)
+    }
+}
+
+impl<T> IsEmpty for BTreeSet<T> {
+    fn is_empty(&self) -> bool {
+        BTreeSet::is_empty(self)
+    }
+}
+
+#[cfg(feature = "regex")]
+#[doc(hidden)]
+pub use regex::Regex;
+
+#[cfg(feature = "regex")]
+#[doc(hidden)]
+pub fn matches_regex(
+    cell: &'static std::sync::OnceLock<Regex>,
+    pattern: &str,
+    value: &str,
+) -> bool {
+    cell.get_or_init(|| Regex::new(pattern).expect("checked by #[derive(ValidateConfig)]"))
+        .is_match(value)
+}
+
+#[cfg(feature = "url")]
+#[doc(hidden)]
+pub fn check_url(value: &str) -> Result<(), url::ParseError> {
+    url::Url::parse(value).map(|_| ())
+}
+
+/// Expands the `regex` rule of `#[derive(ValidateConfig)]`, or names the
+/// missing feature.
+#[cfg(feature = "regex")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __validate_regex {
+    ($($check:tt)*) => { $($check)* };
+}
+
+#[cfg(not(feature = "regex"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __validate_regex {
+    ($($check:tt)*) => {
+        compile_error!("the `regex` validate rule requires the `regex` feature of lolibaso")
+    };
+}
+
+/// Like `__validate_regex`, for the `url` rule.
+#[cfg(feature = "url")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __validate_url {
+    ($($check:tt)*) => { $($check)* };
+}
+
+#[cfg(not(feature = "url"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __validate_url {
+    ($($check:tt)*) => {
+        compile_error!("the `url` validate rule requires the `url` feature of lolibaso")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct PoolConfig {
+        size: u32,
+    }
+
+    struct DbConfig {
+        url: String,
+        pool: PoolConfig,
+    }
+
+    impl ValidateConfig for PoolConfig {
+        fn validate_at(&self, key: &str, errors: &mut ValidationErrors) {
+            if self.size < 1 {
+                errors.push(join_key(key, "size"), "must be at least 1");
+            }
+        }
+    }
+
+    impl ValidateConfig for DbConfig {
+        fn validate_at(&self, key: &str, errors: &mut ValidationErrors) {
+            if IsEmpty::is_empty(&self.url) {
+                errors.push(join_key(key, "url"), "must not be empty");
+            }
+            self.pool.validate_at(&join_key(key, "pool"), errors);
+        }
+    }
+
+    #[test]
+    fn t_violations_aggregated() {
+        let config = DbConfig {
+            url: String::new(),
+            pool: PoolConfig { size: 0 },
+        };
+        let err = check_on_startup(&config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 config violation(s); url: must not be empty; pool.size: must be at least 1"
+        );
+    }
+}
@@ -1,3 +1,34 @@
+//! Snowflake ids, generated by `flake_id!` types.
+//!
+//! Every type has its own generator. Its epoch, bit layout and node id are
+//! set at startup with [`init`]; until then, the default layout and the
+//! `node` given to the macro are used, or generation fails in strict mode.
+//!
+//! With `prefix = "usr"`, ids display, parse and serialize as `usr_` and
+//! the id in [`IdEncoding::Base62`], or in the encoding given by
+//! `encoding = crockford`. Databases still store the `i64`.
+//!
+//! ```ignore
+//! flake_id!(UserId, node = 1, prefix = "usr", @serde, @diesel-pg);
+//! ```
+
+mod atomic;
+mod config;
+mod decode;
+mod generator;
+mod node;
+mod prefixed;
+
+pub use config::{
+    ClockRollback, DEFAULT_EPOCH, FlakeConfig, FlakeLayout, MAX_ROLLBACK_WAIT, NodeStrategy,
+};
+pub use decode::FlakeParts;
+pub use generator::{FlakeGenerator, init, init_with, set_strict};
+pub use node::{HostnameHash, Lease, LeaseFile, NodeIdAllocator, StaticNode};
+#[doc(hidden)]
+pub use prefixed::{IdDeError, is_valid_prefix, parse_prefixed};
+pub use prefixed::{IdEncoding, IdParseError};
+
 #[macro_export]
 macro_rules! flake_id {
     ($type_name:ident $($tt:tt)*) => {
@@ -27,49 +58,132 @@ macro_rules! flake_id {
         }
 
         impl $type_name {
-            pub fn from_str(s: &str) -> Result<Self, std::num::ParseIntError> {
-                Ok(Self(s.parse()?))
+            pub fn from_str(s: &str) -> Result<Self, <Self as ::std::str::FromStr>::Err> {
+                s.parse()
             }
-        }
-    };
 
-    (@id_func $type_name:ident, node = $node:expr, $($tt:tt)*) => {
-        impl $type_name {
+            /// Panics where `try_generate` fails: in strict mode before
+            /// `flake_id::init`, when the clock moved backwards and
+            /// `ClockRollback` does not wait it out, and once the node id
+            /// lease is lost. `Default` and `SysId::generate` call it too.
             pub fn generate() -> $type_name {
-                use $crate::flaken::Flaken;
-                use ::std::sync::{Mutex, OnceLock};
-                static FLAKE_ID_GENERATOR: OnceLock<Mutex<Flaken>> = OnceLock::new();
-                let f = FLAKE_ID_GENERATOR.get_or_init(|| {
-                    let f = $crate::flaken::Flaken::default();
-                    let f = f.node($node as u64);
-                    Mutex::new(f)
-                });
-                let mut lock = f.lock().unwrap();
-                $type_name(lock.next() as i64)
+                Self::try_generate().unwrap_or_else(|err| panic!("{err}"))
+            }
+
+            pub fn try_generate() -> ::anyhow::Result<$type_name> {
+                Ok($type_name(Self::generator().next()?))
+            }
+
+            /// The layout of this type. Panics in strict mode before
+            /// `flake_id::init`, like the methods that decode or encode with
+            /// it. Reading it does not fix the layout before the first id.
+            pub fn layout() -> $crate::flake_id::FlakeLayout {
+                Self::try_layout().unwrap_or_else(|err| panic!("{err}"))
+            }
+
+            pub fn try_layout() -> ::anyhow::Result<$crate::flake_id::FlakeLayout> {
+                Self::generator().layout()
+            }
+
+            /// Displays as `<timestamp> node = <node> sequence = <sequence>`.
+            pub fn decode(&self) -> $crate::flake_id::FlakeParts {
+                Self::layout().decode(self.0)
+            }
+
+            pub fn try_decode(&self) -> ::anyhow::Result<$crate::flake_id::FlakeParts> {
+                Ok(Self::try_layout()?.decode(self.0))
+            }
+
+            pub fn timestamp(&self) -> ::std::time::SystemTime {
+                self.decode().timestamp
+            }
+
+            pub fn node(&self) -> u64 {
+                self.decode().node
+            }
+
+            pub fn sequence(&self) -> u64 {
+                self.decode().sequence
+            }
+
+            /// The smallest id of the millisecond of `time`, e.g. for
+            /// `id >= min_for(since)` range queries.
+            pub fn min_for(time: ::std::time::SystemTime) -> $type_name {
+                $type_name(Self::layout().min_id(time))
+            }
+
+            pub fn max_for(time: ::std::time::SystemTime) -> $type_name {
+                $type_name(Self::layout().max_id(time))
             }
         }
-        $crate::flake_id!(@impl $type_name, {Debug,PartialEq,PartialOrd,Eq,Hash,Clone,Copy,}, {}, $($tt)* ,);
+    };
+
+    (@id_func $type_name:ident, node = $node:expr, $($tt:tt)*) => {
+        $crate::flake_id!(@generator $type_name, Some($node as u64));
+        $crate::flake_id!(@prefix $type_name, $($tt)* ,);
     };
 
     (@id_func $type_name:ident, $($tt:tt)*) => {
-        impl $type_name {
-            pub fn generate() -> $type_name {
-                use $crate::flaken::Flaken;
-                use ::std::sync::{Mutex, OnceLock};
-                static FLAKE_ID_GENERATOR: OnceLock<Mutex<Flaken>> = OnceLock::new();
-                let f = FLAKE_ID_GENERATOR.get_or_init(|| {
-                    let f = $crate::flaken::Flaken::default();
-                    Mutex::new(f)
-                });
-                let mut lock = f.lock().unwrap();
-                $type_name(lock.next() as i64)
+        $crate::flake_id!(@generator $type_name, None);
+        $crate::flake_id!(@prefix $type_name, $($tt)* ,);
+    };
+
+    (@prefix $type_name:ident, prefix = $prefix:literal, encoding = base62, $($tt:tt)*) => {
+        $crate::flake_id!(@prefixed $type_name, $prefix, Base62, $($tt)*);
+    };
+
+    (@prefix $type_name:ident, prefix = $prefix:literal, encoding = crockford, $($tt:tt)*) => {
+        $crate::flake_id!(@prefixed $type_name, $prefix, Crockford, $($tt)*);
+    };
+
+    (@prefix $type_name:ident, prefix = $prefix:literal, $($tt:tt)*) => {
+        $crate::flake_id!(@prefixed $type_name, $prefix, Base62, $($tt)*);
+    };
+
+    (@prefix $type_name:ident, $($tt:tt)*) => {
+        $crate::flake_id!(@impl $type_name,
+            {Debug,PartialEq,PartialOrd,Eq,Hash,Clone,Copy, $crate::derive_more::Display, $crate::derive_more::FromStr,},
+            {},
+            $($tt)* ,
+        );
+    };
+
+    // Displays as `<prefix>_<encoded id>` instead of the number.
+    (@prefixed $type_name:ident, $prefix:literal, $encoding:ident, $($tt:tt)*) => {
+        const _: () = assert!(
+            $crate::flake_id::is_valid_prefix($prefix),
+            "flake_id!: prefix must be non-empty and must not contain `_`"
+        );
+
+        impl ::std::fmt::Display for $type_name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                let encoding = $crate::flake_id::IdEncoding::$encoding;
+                write!(f, "{}_{}", $prefix, encoding.encode(self.0))
             }
         }
 
+        impl ::std::str::FromStr for $type_name {
+            type Err = $crate::flake_id::IdParseError;
+
+            fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+                let encoding = $crate::flake_id::IdEncoding::$encoding;
+                $crate::flake_id::parse_prefixed(stringify!($type_name), $prefix, encoding, s)
+                    .map($type_name)
+            }
+        }
 
         $crate::flake_id!(@impl $type_name, {Debug,PartialEq,PartialOrd,Eq,Hash,Clone,Copy,}, {}, $($tt)* ,);
     };
 
+    (@generator $type_name:ident, $node:expr) => {
+        impl $type_name {
+            pub fn generator() -> &'static $crate::flake_id::FlakeGenerator {
+                static GENERATOR: $crate::flake_id::FlakeGenerator =
+                    $crate::flake_id::FlakeGenerator::new(stringify!($type_name), $node);
+                &GENERATOR
+            }
+        }
+    };
 
     (@impl $type_name:ident, {$($derives:tt)*}, {$($attrs:tt)*}, @redis, $($tail:tt)*) => {
         impl ::redis::ToRedisArgs for $type_name {
@@ -97,7 +211,7 @@ macro_rules! flake_id {
             where
                 S: ::serde::Serializer,
             {
-                serializer.serialize_str(&self.0.to_string())
+                serializer.collect_str(self)
             }
         }
 
@@ -107,8 +221,9 @@ macro_rules! flake_id {
                 D: ::serde::Deserializer<'de>,
             {
                 let id = String::deserialize(deserializer)?;
-                let id = id.parse().map_err(serde::de::Error::custom)?;
-                Ok(Self(id))
+                id.parse().map_err(|err| {
+                    serde::de::Error::custom($crate::flake_id::IdDeError::de_message(&err))
+                })
             }
         }
 
@@ -116,6 +231,38 @@ macro_rules! flake_id {
     };
 
     (@impl $type_name:ident, {$($derives:tt)*}, {$($attrs:tt)*}, @diesel-pg, $($tail:tt)*) => {
+        $crate::flake_id!(@diesel $type_name, ::diesel::pg::Pg, {$($derives)*}, {$($attrs)*}, $($tail)* ,);
+    };
+
+    (@impl $type_name:ident, {$($derives:tt)*}, {$($attrs:tt)*}, @diesel-sqlite, $($tail:tt)*) => {
+        $crate::flake_id!(@diesel $type_name, ::diesel::sqlite::Sqlite, {$($derives)*}, {$($attrs)*}, $($tail)* ,);
+    };
+
+    (@impl $type_name:ident, {$($derives:tt)*}, {$($attrs:tt)*}, @diesel-mysql, $($tail:tt)*) => {
+        $crate::flake_id!(@diesel $type_name, ::diesel::mysql::Mysql, {$($derives)*}, {$($attrs)*}, $($tail)* ,);
+    };
+
+    // The diesel derives are shared by every backend, so only the first
+    // backend adds them.
+    (@diesel $type_name:ident, $db:ty, {::diesel::AsExpression, ::diesel::FromSqlRow, $($derives:tt)*}, {$($attrs:tt)*}, $($tail:tt)*) => {
+        $crate::flake_id!(@diesel_sql $type_name, $db);
+        $crate::flake_id!(@impl $type_name,
+            {::diesel::AsExpression, ::diesel::FromSqlRow, $($derives)*},
+            {$($attrs)*},
+            $($tail)* ,
+        );
+    };
+
+    (@diesel $type_name:ident, $db:ty, {$($derives:tt)*}, {$($attrs:tt)*}, $($tail:tt)*) => {
+        $crate::flake_id!(@diesel_sql $type_name, $db);
+        $crate::flake_id!(@impl $type_name,
+            {::diesel::AsExpression, ::diesel::FromSqlRow, $($derives)*},
+            {$($attrs)* #[diesel(sql_type = ::diesel::sql_types::BigInt)] },
+            $($tail)* ,
+        );
+    };
+
+    (@diesel_sql $type_name:ident, $db:ty) => {
         const _: () = {
             use ::diesel::{
                 backend::Backend,
@@ -123,7 +270,7 @@ macro_rules! flake_id {
                 sql_types::BigInt,
                 serialize::{self, Output, ToSql},
             };
-            type DbType = ::diesel::pg::Pg;
+            type DbType = $db;
 
             impl ToSql<BigInt, DbType> for $type_name {
                 fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DbType>) -> serialize::Result {
@@ -138,11 +285,47 @@ macro_rules! flake_id {
                 }
             }
         };
-        $crate::flake_id!(@impl $type_name,
-            {$($derives)* ::diesel:: AsExpression, ::diesel::FromSqlRow,},
-            {$($attrs)* #[diesel(sql_type = ::diesel::sql_types::BigInt)] },
-             $($tail)* ,
-        );
+    };
+
+    (@impl $type_name:ident, {$($derives:tt)*}, {$($attrs:tt)*}, @sqlx, $($tail:tt)*) => {
+        const _: () = {
+            use ::sqlx::{Database, Decode, Encode, Type, encode::IsNull, error::BoxDynError};
+
+            impl<DB: Database> Type<DB> for $type_name
+            where
+                i64: Type<DB>,
+            {
+                fn type_info() -> DB::TypeInfo {
+                    <i64 as Type<DB>>::type_info()
+                }
+
+                fn compatible(ty: &DB::TypeInfo) -> bool {
+                    <i64 as Type<DB>>::compatible(ty)
+                }
+            }
+
+            impl<'q, DB: Database> Encode<'q, DB> for $type_name
+            where
+                i64: Encode<'q, DB>,
+            {
+                fn encode_by_ref(
+                    &self,
+                    buf: &mut <DB as Database>::ArgumentBuffer<'q>,
+                ) -> Result<IsNull, BoxDynError> {
+                    <i64 as Encode<'q, DB>>::encode_by_ref(&self.0, buf)
+                }
+            }
+
+            impl<'r, DB: Database> Decode<'r, DB> for $type_name
+            where
+                i64: Decode<'r, DB>,
+            {
+                fn decode(value: <DB as Database>::ValueRef<'r>) -> Result<Self, BoxDynError> {
+                    Ok(Self(<i64 as Decode<'r, DB>>::decode(value)?))
+                }
+            }
+        };
+        $crate::flake_id!(@impl $type_name, {$($derives)*}, {$($attrs)*}, $($tail)* ,);
     };
 
     (@impl $type_name:ident, {$($derives:tt)*}, {$($attrs:tt)*}, @graphql, $($tail:tt)*) => {
@@ -160,13 +343,14 @@ macro_rules! flake_id {
 
 
     (@impl $type_name:ident, {$($derives:tt)*}, {$($attrs:tt)*}, $(,)*) => {
+        // The derive_more derives name `derive_more` unqualified. A glob
+        // import does not clash with other id types in the same module.
+        #[allow(unused_imports)]
         use $crate::*;
 
         #[derive(
         $($derives)*
         $crate::derive_more::From,
-        $crate::derive_more::Display,
-        $crate::derive_more::FromStr,
         )]
         $($attrs)*
         pub struct $type_name(pub i64);
@@ -187,12 +371,97 @@ macro_rules! un_derive {
 
 #[cfg(test)]
 mod tests {
+    use diesel::prelude::*;
+
     flake_id!(UserId);
+    flake_id!(PostId, @diesel-sqlite, @diesel-mysql, @sqlx);
+    flake_id!(OrgId, prefix = "org", @serde, @diesel-sqlite);
+    flake_id!(TeamId, node = 2, prefix = "team", encoding = crockford);
+
+    diesel::table! {
+        posts (id) {
+            id -> BigInt,
+        }
+    }
 
     #[test]
     fn t_flake_id() {
         let id1 = UserId::generate();
         let id2 = UserId::generate();
         assert_ne!(id1, id2);
+
+        let time = id1.timestamp();
+        assert!(UserId::min_for(time).0 <= id1.0 && id1.0 <= UserId::max_for(time).0);
+        assert_eq!(id1.node(), UserId::layout().node);
+        assert_eq!(id1.try_decode().unwrap(), id1.decode());
+    }
+
+    #[test]
+    fn t_prefixed_id() {
+        let org = OrgId::generate();
+        let s = org.to_string();
+        assert!(s.starts_with("org_") && s.len() == 15);
+        assert_eq!(s.parse::<OrgId>().unwrap(), org);
+        assert_eq!(serde_json::to_string(&org).unwrap(), format!("\"{s}\""));
+
+        let team = TeamId::generate();
+        assert_eq!(team.to_string().len(), 18);
+        assert_eq!(TeamId::from_str(&team.to_string()).unwrap(), team);
+
+        let err = TeamId::from_str(&s).unwrap_err();
+        assert_eq!(err.err_name(), "InvalidTeamId");
+        assert_eq!(err.to_string(), "invalid TeamId: expected prefix `team_`");
+        let err = serde_json::from_str::<OrgId>("\"org_!\"").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("##InvalidOrgId(expected 11 characters)##")
+        );
+    }
+
+    #[test]
+    fn t_diesel_sqlite() {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        diesel::sql_query("CREATE TABLE posts (id BIGINT PRIMARY KEY)")
+            .execute(&mut conn)
+            .unwrap();
+
+        let id = PostId::generate();
+        diesel::insert_into(posts::table)
+            .values(posts::id.eq(id))
+            .execute(&mut conn)
+            .unwrap();
+        let loaded: PostId = posts::table
+            .select(posts::id)
+            .filter(posts::id.eq(id))
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!(loaded, id);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn t_sqlx_sqlite() {
+        use sqlx::Connection;
+
+        let mut conn = sqlx::SqliteConnection::connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE posts (id BIGINT PRIMARY KEY)")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let id = PostId::generate();
+        sqlx::query("INSERT INTO posts (id) VALUES (?)")
+            .bind(id)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let loaded: PostId = sqlx::query_scalar("SELECT id FROM posts WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(loaded, id);
     }
 }
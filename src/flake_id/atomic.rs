@@ -0,0 +1,171 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::config::{ClockRollback, FlakeLayout, MAX_ROLLBACK_WAIT};
+
+/// Generates the ids of one layout without locking.
+///
+/// The timestamp and sequence of the last id are packed into one atomic and
+/// advanced by compare-and-swap, so concurrent callers retry instead of
+/// blocking each other.
+pub(crate) struct AtomicFlake {
+    layout: FlakeLayout,
+    last: AtomicU64,
+}
+
+impl AtomicFlake {
+    pub(crate) fn new(layout: FlakeLayout) -> Self {
+        AtomicFlake {
+            layout,
+            last: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn layout(&self) -> &FlakeLayout {
+        &self.layout
+    }
+
+    pub(crate) fn next(&self) -> anyhow::Result<i64> {
+        let epoch = self.layout.epoch;
+        self.next_with(|| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            (now.as_millis() as u64).saturating_sub(epoch)
+        })
+    }
+
+    /// `now` returns the milliseconds since the epoch of the layout.
+    fn next_with(&self, now: impl Fn() -> u64) -> anyhow::Result<i64> {
+        let sequence_bits = self.layout.sequence_bits();
+        let sequence_max = (1 << sequence_bits) - 1;
+        let mut last = self.last.load(Ordering::Relaxed);
+        loop {
+            let now = now();
+            let (last_ts, last_seq) = (last >> sequence_bits, last & sequence_max);
+            let (ts, seq) = if now > last_ts {
+                (now, 0)
+            } else if now < last_ts && self.layout.clock_rollback != ClockRollback::Borrow {
+                let behind = last_ts - now;
+                if self.layout.clock_rollback == ClockRollback::Error {
+                    anyhow::bail!(
+                        "FlakeGenerator::next: clock moved backwards. behind_ms = {}",
+                        behind
+                    );
+                }
+                let wait = Duration::from_millis(behind);
+                anyhow::ensure!(
+                    wait <= MAX_ROLLBACK_WAIT,
+                    "FlakeGenerator::next: clock moved backwards too far to wait. behind_ms = {}. max_wait_ms = {}",
+                    behind,
+                    MAX_ROLLBACK_WAIT.as_millis()
+                );
+                std::thread::sleep(wait);
+                last = self.last.load(Ordering::Relaxed);
+                continue;
+            } else if last_seq < sequence_max {
+                (last_ts, last_seq + 1)
+            } else if now < last_ts {
+                (last_ts + 1, 0)
+            } else {
+                // The sequence of this millisecond is used up.
+                std::hint::spin_loop();
+                last = self.last.load(Ordering::Relaxed);
+                continue;
+            };
+
+            // Uniqueness only depends on the order of updates to `last`
+            // itself, which every ordering guarantees.
+            let next = ts << sequence_bits | seq;
+            match self
+                .last
+                .compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return Ok(self.encode(ts, seq)),
+                Err(actual) => last = actual,
+            }
+        }
+    }
+
+    fn encode(&self, ts: u64, seq: u64) -> i64 {
+        let FlakeLayout {
+            timestamp_bits,
+            node_bits,
+            node,
+            ..
+        } = self.layout;
+        let sequence_bits = self.layout.sequence_bits();
+        let ts = ts & ((1 << timestamp_bits) - 1);
+        (ts << (node_bits + sequence_bits) | node << sequence_bits | seq) as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::HashSet, sync::Arc};
+
+    use super::*;
+
+    fn flake(clock_rollback: ClockRollback) -> AtomicFlake {
+        AtomicFlake::new(FlakeLayout {
+            node: 5,
+            clock_rollback,
+            ..Default::default()
+        })
+    }
+
+    fn parts(flake: &AtomicFlake, id: anyhow::Result<i64>) -> (u64, u64) {
+        let id = id.unwrap() as u64;
+        let sequence_bits = flake.layout.sequence_bits();
+        (id >> 22, id & ((1 << sequence_bits) - 1))
+    }
+
+    #[test]
+    fn t_clock_rollback() {
+        let borrow = flake(ClockRollback::Borrow);
+        assert_eq!(parts(&borrow, borrow.next_with(|| 100)), (100, 0));
+        assert_eq!(parts(&borrow, borrow.next_with(|| 100)), (100, 1));
+        assert_eq!(parts(&borrow, borrow.next_with(|| 99)), (100, 2));
+
+        let error = flake(ClockRollback::Error);
+        error.next_with(|| 100).unwrap();
+        assert!(error.next_with(|| 99).is_err());
+
+        let wait = flake(ClockRollback::Wait);
+        wait.next_with(|| 100).unwrap();
+        let clock = Cell::new(98);
+        let id = wait.next_with(|| {
+            clock.set(clock.get() + 1);
+            clock.get()
+        });
+        assert_eq!(parts(&wait, id), (100, 1));
+
+        let max_wait = MAX_ROLLBACK_WAIT.as_millis() as u64;
+        wait.next_with(|| 100 + max_wait + 1).unwrap();
+        assert!(wait.next_with(|| 100).is_err());
+    }
+
+    #[test]
+    fn t_concurrent_ids_unique() {
+        let flake = Arc::new(flake(ClockRollback::Wait));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let flake = flake.clone();
+                std::thread::spawn(move || {
+                    (0..10_000)
+                        .map(|_| flake.next().unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut ids = HashSet::new();
+        for thread in threads {
+            for id in thread.join().unwrap() {
+                assert!(ids.insert(id));
+                assert_eq!((id as u64 >> 12) & 0x3ff, 5);
+            }
+        }
+    }
+}
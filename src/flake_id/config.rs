@@ -0,0 +1,182 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::node::{HostnameHash, LeaseFile, NodeIdAllocator, StaticNode};
+
+/// 2013-01-01T00:00:00Z, the epoch of `Flaken::default()`.
+pub const DEFAULT_EPOCH: u64 = 1_356_998_400_000;
+
+/// Startup settings of `flake_id!` generators, usually a section of the app
+/// config, or loaded on its own:
+///
+/// ```ignore
+/// let config: FlakeConfig = ConfigLoader::new().env_prefix("FLAKE").load()?;
+/// // FLAKE_NODE_STRATEGY=lease FLAKE_LEASE_DIR=/shared/flake-id
+/// flake_id::init(&config)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlakeConfig {
+    /// Milliseconds since the unix epoch that timestamps count from.
+    pub epoch: u64,
+    pub timestamp_bits: u8,
+    pub node_bits: u8,
+    pub node_strategy: NodeStrategy,
+    /// The node id of the `static` strategy.
+    pub node_id: u64,
+    /// The directory shared by all replicas with the `lease` strategy.
+    pub lease_dir: PathBuf,
+    /// Leases not refreshed for this long are taken over.
+    pub lease_ttl_secs: u64,
+    pub clock_rollback: ClockRollback,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeStrategy {
+    /// [`StaticNode`]
+    #[default]
+    Static,
+    /// [`HostnameHash`]
+    Hostname,
+    /// [`LeaseFile`]
+    Lease,
+}
+
+/// How long [`ClockRollback::Wait`] sleeps for the clock to catch up before
+/// failing instead.
+pub const MAX_ROLLBACK_WAIT: Duration = Duration::from_secs(1);
+
+/// What a generator does when the system clock is behind the timestamp of
+/// its last id, e.g. after an NTP step.
+///
+/// Where generation fails, `generate()`, `Default` and `SysId::generate` of
+/// `flake_id!` types panic; use `try_generate()` to handle the error.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockRollback {
+    /// Sleeps until the clock catches up, and fails if it is more than
+    /// [`MAX_ROLLBACK_WAIT`] behind.
+    #[default]
+    Wait,
+    /// Keeps the last timestamp and takes ids from its sequence, moving on to
+    /// the next millisecond when the sequence runs out.
+    Borrow,
+    /// Fails to generate until the clock catches up.
+    Error,
+}
+
+impl Default for FlakeConfig {
+    fn default() -> Self {
+        let layout = FlakeLayout::default();
+        FlakeConfig {
+            epoch: layout.epoch,
+            timestamp_bits: layout.timestamp_bits,
+            node_bits: layout.node_bits,
+            node_strategy: NodeStrategy::Static,
+            node_id: 0,
+            lease_dir: PathBuf::new(),
+            lease_ttl_secs: 60,
+            clock_rollback: layout.clock_rollback,
+        }
+    }
+}
+
+impl FlakeConfig {
+    /// The allocator of `node_strategy`.
+    pub fn allocator(&self) -> anyhow::Result<Box<dyn NodeIdAllocator>> {
+        Ok(match self.node_strategy {
+            NodeStrategy::Static => Box::new(StaticNode(self.node_id)),
+            NodeStrategy::Hostname => Box::new(HostnameHash),
+            NodeStrategy::Lease => {
+                anyhow::ensure!(
+                    !self.lease_dir.as_os_str().is_empty(),
+                    "FlakeConfig::allocator: lease_dir is required by the lease strategy"
+                );
+                Box::new(LeaseFile::new(
+                    &self.lease_dir,
+                    Duration::from_secs(self.lease_ttl_secs),
+                ))
+            }
+        })
+    }
+
+    /// Checks the layout and allocates the node id with `node_strategy`.
+    pub fn resolve(&self) -> anyhow::Result<FlakeLayout> {
+        self.resolve_with(self.allocator()?.as_ref())
+    }
+
+    pub fn resolve_with(&self, allocator: &dyn NodeIdAllocator) -> anyhow::Result<FlakeLayout> {
+        let mut layout = FlakeLayout {
+            epoch: self.epoch,
+            timestamp_bits: self.timestamp_bits,
+            node_bits: self.node_bits,
+            node: 0,
+            clock_rollback: self.clock_rollback,
+        };
+        layout.validate()?;
+        layout.node = allocator.allocate(self.node_bits)?;
+        layout.validate()?;
+        Ok(layout)
+    }
+}
+
+/// A checked [`FlakeConfig`] with its node id allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlakeLayout {
+    pub epoch: u64,
+    pub timestamp_bits: u8,
+    pub node_bits: u8,
+    pub node: u64,
+    pub clock_rollback: ClockRollback,
+}
+
+impl Default for FlakeLayout {
+    /// The layout of `Flaken::default()`: 42 timestamp, 10 node and 12
+    /// sequence bits.
+    fn default() -> Self {
+        FlakeLayout {
+            epoch: DEFAULT_EPOCH,
+            timestamp_bits: 42,
+            node_bits: 10,
+            node: 0,
+            clock_rollback: ClockRollback::Wait,
+        }
+    }
+}
+
+impl FlakeLayout {
+    /// Checks that the bits leave room for a sequence, that the epoch is not
+    /// in the future and that the node id fits in `node_bits`.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            u32::from(self.timestamp_bits) + u32::from(self.node_bits) < 64,
+            "FlakeLayout::validate: no bits left for the sequence. timestamp_bits = {}. node_bits = {}",
+            self.timestamp_bits,
+            self.node_bits
+        );
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        anyhow::ensure!(
+            u128::from(self.epoch) <= now,
+            "FlakeLayout::validate: epoch is in the future. epoch = {}",
+            self.epoch
+        );
+        anyhow::ensure!(
+            self.node < 1 << self.node_bits,
+            "FlakeLayout::validate: node id does not fit in node_bits. node = {}. node_bits = {}",
+            self.node,
+            self.node_bits
+        );
+        Ok(())
+    }
+
+    /// The bits left for the sequence, 0 if the layout fails
+    /// [`FlakeLayout::validate`].
+    pub fn sequence_bits(&self) -> u8 {
+        64u8.saturating_sub(self.timestamp_bits.saturating_add(self.node_bits))
+    }
+}
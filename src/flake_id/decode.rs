@@ -0,0 +1,135 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::config::FlakeLayout;
+
+/// The parts of a flake id. Displays as
+/// `2026-10-18T09:30:00.125Z node = 3 sequence = 17`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlakeParts {
+    pub timestamp: SystemTime,
+    pub node: u64,
+    pub sequence: u64,
+}
+
+impl FlakeLayout {
+    pub fn decode(&self, id: i64) -> FlakeParts {
+        let id = id as u64;
+        let sequence_bits = self.sequence_bits();
+        let millis = (id >> (sequence_bits + self.node_bits)) & mask(self.timestamp_bits);
+        FlakeParts {
+            timestamp: UNIX_EPOCH + Duration::from_millis(self.epoch + millis),
+            node: (id >> sequence_bits) & mask(self.node_bits),
+            sequence: id & mask(sequence_bits),
+        }
+    }
+
+    /// The id of `parts`. Times before the epoch encode as the epoch, and
+    /// node and sequence are truncated to their bits.
+    pub fn encode(&self, parts: FlakeParts) -> i64 {
+        let millis = parts
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64)
+            .saturating_sub(self.epoch)
+            .min(mask(self.timestamp_bits));
+        let sequence_bits = self.sequence_bits();
+        let id = millis << (sequence_bits + self.node_bits)
+            | (parts.node & mask(self.node_bits)) << sequence_bits
+            | parts.sequence & mask(sequence_bits);
+        id as i64
+    }
+
+    /// The smallest id generated within the millisecond of `time`, as the
+    /// lower bound of a range query.
+    pub fn min_id(&self, time: SystemTime) -> i64 {
+        self.encode(FlakeParts {
+            timestamp: time,
+            node: 0,
+            sequence: 0,
+        })
+    }
+
+    /// The largest id generated within the millisecond of `time`.
+    pub fn max_id(&self, time: SystemTime) -> i64 {
+        self.encode(FlakeParts {
+            timestamp: time,
+            node: u64::MAX,
+            sequence: u64::MAX,
+        })
+    }
+}
+
+fn mask(bits: u8) -> u64 {
+    (1 << bits) - 1
+}
+
+impl fmt::Display for FlakeParts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        let (secs, millis) = (millis / 1000, millis % 1000);
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let secs = secs % 86_400;
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{millis:03}Z node = {} sequence = {}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            self.node,
+            self.sequence
+        )
+    }
+}
+
+/// The date of `days` since 1970-01-01, after Howard Hinnant's
+/// `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_decode() {
+        let layout = FlakeLayout {
+            node: 3,
+            ..Default::default()
+        };
+        let time = UNIX_EPOCH + Duration::from_millis(1_792_315_800_125);
+        let parts = FlakeParts {
+            timestamp: time,
+            node: 3,
+            sequence: 17,
+        };
+        let id = layout.encode(parts);
+        assert_eq!(layout.decode(id), parts);
+        assert_eq!(
+            parts.to_string(),
+            "2026-10-18T09:30:00.125Z node = 3 sequence = 17"
+        );
+
+        assert!(layout.min_id(time) < id && id < layout.max_id(time));
+        assert_eq!(
+            layout.max_id(time) + 1,
+            layout.min_id(time + Duration::from_millis(1))
+        );
+        assert_eq!(layout.min_id(UNIX_EPOCH), 0);
+    }
+}
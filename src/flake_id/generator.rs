@@ -0,0 +1,196 @@
+use std::sync::{
+    OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+
+use super::atomic::AtomicFlake;
+use super::config::{FlakeConfig, FlakeLayout};
+use super::node::{NodeIdAllocator, lease_lost};
+
+static LAYOUT: OnceLock<FlakeLayout> = OnceLock::new();
+static STRICT: AtomicBool = AtomicBool::new(false);
+static DEFAULTED: AtomicBool = AtomicBool::new(false);
+
+/// Sets the layout of every `flake_id!` type that has not generated an id
+/// yet. Call it once at startup.
+pub fn init(config: &FlakeConfig) -> anyhow::Result<FlakeLayout> {
+    init_with(config, config.allocator()?.as_ref())
+}
+
+/// Like [`init`], allocating the node id with `allocator`.
+pub fn init_with(
+    config: &FlakeConfig,
+    allocator: &dyn NodeIdAllocator,
+) -> anyhow::Result<FlakeLayout> {
+    let layout = config.resolve_with(allocator)?;
+    LAYOUT
+        .set(layout)
+        .map_err(|_| anyhow::anyhow!("flake_id::init: already initialised"))?;
+    if DEFAULTED.load(Ordering::Relaxed) {
+        tracing::warn!("flake_id::init: ids were generated before init with the default layout");
+    }
+    Ok(layout)
+}
+
+/// In strict mode, generating an id before [`init`] is an error instead of
+/// falling back to the default layout and the `node` given to `flake_id!`.
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// The generator behind a `flake_id!` type, returned by its `generator()`.
+pub struct FlakeGenerator {
+    name: &'static str,
+    node: Option<u64>,
+    state: OnceLock<AtomicFlake>,
+}
+
+impl FlakeGenerator {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str, node: Option<u64>) -> Self {
+        FlakeGenerator {
+            name,
+            node,
+            state: OnceLock::new(),
+        }
+    }
+
+    /// Gives this type its own layout instead of the one set by [`init`].
+    /// Fails if the layout does not pass [`FlakeLayout::validate`].
+    pub fn init(&self, layout: FlakeLayout) -> anyhow::Result<()> {
+        layout.validate()?;
+        self.state.set(AtomicFlake::new(layout)).map_err(|_| {
+            anyhow::anyhow!(
+                "FlakeGenerator::init: already initialised or used. type = {}",
+                self.name
+            )
+        })
+    }
+
+    /// The layout ids are generated with. Before the first id it is only
+    /// looked up, so a later [`init`] or [`FlakeGenerator::init`] still
+    /// applies.
+    pub fn layout(&self) -> anyhow::Result<FlakeLayout> {
+        match self.state.get() {
+            Some(state) => Ok(*state.layout()),
+            None => fallback_layout(
+                "FlakeGenerator::layout",
+                self.name,
+                self.node,
+                LAYOUT.get(),
+                STRICT.load(Ordering::Relaxed),
+            ),
+        }
+    }
+
+    /// Fails in strict mode before [`init`], when the clock moved backwards
+    /// under [`ClockRollback::Error`](super::ClockRollback::Error), and once
+    /// the node id lease of a [`LeaseFile`](super::LeaseFile) is lost.
+    pub fn next(&self) -> anyhow::Result<i64> {
+        anyhow::ensure!(
+            !lease_lost(),
+            "FlakeGenerator::next: node id lease lost. type = {}",
+            self.name
+        );
+        self.state()?.next()
+    }
+
+    fn state(&self) -> anyhow::Result<&AtomicFlake> {
+        if let Some(state) = self.state.get() {
+            return Ok(state);
+        }
+        let layout = LAYOUT.get();
+        let strict = STRICT.load(Ordering::Relaxed);
+        let fallback =
+            fallback_layout("FlakeGenerator::next", self.name, self.node, layout, strict)?;
+        if layout.is_none() {
+            DEFAULTED.store(true, Ordering::Relaxed);
+        }
+        Ok(self.state.get_or_init(|| AtomicFlake::new(fallback)))
+    }
+}
+
+fn fallback_layout(
+    method: &str,
+    name: &str,
+    node: Option<u64>,
+    layout: Option<&FlakeLayout>,
+    strict: bool,
+) -> anyhow::Result<FlakeLayout> {
+    match layout {
+        Some(layout) => Ok(*layout),
+        None if strict => anyhow::bail!(
+            "{}: used before flake_id::init in strict mode. type = {}",
+            method,
+            name
+        ),
+        None => Ok(FlakeLayout {
+            node: node.unwrap_or(0),
+            ..FlakeLayout::default()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flake_id::StaticNode;
+
+    #[test]
+    fn t_generator_layout() {
+        let config = FlakeConfig {
+            epoch: 1_700_000_000_000,
+            timestamp_bits: 41,
+            node_bits: 8,
+            ..Default::default()
+        };
+        let layout = config.resolve_with(&StaticNode(7)).unwrap();
+
+        let generator = FlakeGenerator::new("OrderId", Some(1));
+        generator.init(layout).unwrap();
+        assert!(generator.init(layout).is_err());
+        let id = generator.next().unwrap();
+        assert_eq!(layout.decode(id).node, 7);
+
+        assert!(config.resolve_with(&StaticNode(256)).is_err());
+        let invalid = FlakeGenerator::new("InvoiceId", None);
+        let too_wide = FlakeLayout {
+            timestamp_bits: 60,
+            node_bits: 10,
+            ..layout
+        };
+        assert_eq!(too_wide.sequence_bits(), 0);
+        let err = invalid.init(too_wide).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "FlakeLayout::validate: no bits left for the sequence. timestamp_bits = 60. node_bits = 10"
+        );
+        assert!(
+            invalid
+                .init(FlakeLayout {
+                    node: 256,
+                    ..layout
+                })
+                .is_err()
+        );
+        invalid.init(layout).unwrap();
+        assert!(fallback_layout("FlakeGenerator::next", "OrderId", Some(1), None, true).is_err());
+        assert_eq!(
+            fallback_layout("FlakeGenerator::next", "OrderId", Some(1), None, false)
+                .unwrap()
+                .node,
+            1
+        );
+    }
+
+    #[test]
+    fn t_layout_before_init() {
+        let generator = FlakeGenerator::new("RefundId", Some(3));
+        assert_eq!(generator.layout().unwrap().node, 3);
+
+        let layout = FlakeConfig::default().resolve_with(&StaticNode(9)).unwrap();
+        generator.init(layout).unwrap();
+        assert_eq!(generator.layout().unwrap(), layout);
+        assert_eq!(layout.decode(generator.next().unwrap()).node, 9);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+/// Picks the node id of this process, so that replicas generate distinct
+/// ids.
+pub trait NodeIdAllocator {
+    /// Returns a node id below `1 << node_bits`.
+    fn allocate(&self, node_bits: u8) -> anyhow::Result<u64>;
+}
+
+/// A fixed node id, e.g. the ordinal of a stateful set.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticNode(pub u64);
+
+impl NodeIdAllocator for StaticNode {
+    fn allocate(&self, _node_bits: u8) -> anyhow::Result<u64> {
+        Ok(self.0)
+    }
+}
+
+/// The hash of the hostname. Distinct hostnames may collide, more likely
+/// with fewer node bits.
+#[derive(Debug, Clone, Copy)]
+pub struct HostnameHash;
+
+impl NodeIdAllocator for HostnameHash {
+    fn allocate(&self, node_bits: u8) -> anyhow::Result<u64> {
+        Ok(fnv1a(hostname()?.as_bytes()) & ((1 << node_bits) - 1))
+    }
+}
+
+/// Set when a lease taken by [`LeaseFile`] as a [`NodeIdAllocator`] is lost,
+/// after which another replica may generate with the same node id.
+static LEASE_LOST: AtomicBool = AtomicBool::new(false);
+
+pub(super) fn lease_lost() -> bool {
+    LEASE_LOST.load(Ordering::Relaxed)
+}
+
+/// The lowest node id without a live lease in a directory shared by all
+/// replicas.
+///
+/// The lease is the file `node-<id>.lease`, refreshed by a background thread
+/// every third of `ttl`. A lease not refreshed within `ttl`, e.g. of a
+/// crashed replica, is taken over. Leases are taken and refreshed while
+/// holding a lock on the file `.lock`, so two replicas never take over the
+/// same stale lease.
+///
+/// Once the lease of the allocated node id is lost, generating ids fails.
+#[derive(Debug, Clone)]
+pub struct LeaseFile {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl LeaseFile {
+    pub fn new(dir: impl AsRef<Path>, ttl: Duration) -> Self {
+        LeaseFile {
+            dir: dir.as_ref().to_path_buf(),
+            ttl,
+        }
+    }
+
+    /// Takes the lowest free node id. The lease is refreshed until the
+    /// returned [`Lease`] is dropped.
+    pub fn acquire(&self, node_bits: u8) -> anyhow::Result<Lease> {
+        fs::create_dir_all(&self.dir)?;
+        let owner = format!("{} {}", hostname().unwrap_or_default(), std::process::id());
+        let _lock = lock_dir(&self.dir)?;
+
+        for node in 0..1u64 << node_bits {
+            let path = self.dir.join(format!("node-{node}.lease"));
+            if self.is_stale(&path) {
+                let _ = fs::remove_file(&path);
+            }
+            let mut file = match fs::File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => {
+                    anyhow::bail!("LeaseFile::acquire: {err}. path = {}", path.display());
+                }
+            };
+            // A distinct owner per lease, so that a lease taken over by
+            // another lease of this process is noticed too.
+            let owner = format!("{owner} {node}");
+            file.write_all(owner.as_bytes())?;
+            return Ok(Lease::keep_alive(
+                node,
+                LeaseState::new(self.dir.clone(), path, owner),
+                self.ttl / 3,
+            ));
+        }
+
+        anyhow::bail!(
+            "LeaseFile::acquire: every node id is leased. dir = {}",
+            self.dir.display()
+        )
+    }
+
+    fn is_stale(&self, path: &Path) -> bool {
+        fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > self.ttl))
+    }
+}
+
+impl NodeIdAllocator for LeaseFile {
+    /// Keeps the lease for the rest of the process.
+    fn allocate(&self, node_bits: u8) -> anyhow::Result<u64> {
+        let lease = self.acquire(node_bits)?;
+        let node = lease.node;
+        lease.detach();
+        Ok(node)
+    }
+}
+
+/// A node id leased by [`LeaseFile::acquire`]. Dropping it stops refreshing
+/// the lease, which expires after the ttl.
+#[derive(Debug)]
+pub struct Lease {
+    node: u64,
+    state: Arc<LeaseState>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct LeaseState {
+    dir: PathBuf,
+    path: PathBuf,
+    owner: String,
+    stopped: AtomicBool,
+    lost: AtomicBool,
+    /// Whether losing the lease fails generation, see [`lease_lost`].
+    detached: AtomicBool,
+}
+
+impl LeaseState {
+    fn new(dir: PathBuf, path: PathBuf, owner: String) -> Self {
+        LeaseState {
+            dir,
+            path,
+            owner,
+            stopped: AtomicBool::new(false),
+            lost: AtomicBool::new(false),
+            detached: AtomicBool::new(false),
+        }
+    }
+
+    /// Refreshes the lease once, returning whether it is still held.
+    fn refresh(&self) -> bool {
+        match refresh(&self.dir, &self.path, &self.owner) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::error!("node id lease lost: {err}. path = {}", self.path.display());
+                self.lost.store(true, Ordering::Relaxed);
+                if self.detached.load(Ordering::Relaxed) {
+                    LEASE_LOST.store(true, Ordering::Relaxed);
+                }
+                false
+            }
+        }
+    }
+}
+
+impl Lease {
+    fn keep_alive(node: u64, state: LeaseState, every: Duration) -> Self {
+        let state = Arc::new(state);
+        let thread = {
+            let state = state.clone();
+            std::thread::spawn(move || {
+                loop {
+                    std::thread::park_timeout(every);
+                    if state.stopped.load(Ordering::Relaxed) || !state.refresh() {
+                        return;
+                    }
+                }
+            })
+        };
+        Lease {
+            node,
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn node(&self) -> u64 {
+        self.node
+    }
+
+    /// Whether another replica took the lease over, e.g. after this process
+    /// failed to refresh it within the ttl.
+    pub fn is_lost(&self) -> bool {
+        self.state.lost.load(Ordering::Relaxed)
+    }
+
+    /// Refreshes the lease for the rest of the process.
+    fn detach(mut self) {
+        self.state.detached.store(true, Ordering::Relaxed);
+        if self.is_lost() {
+            LEASE_LOST.store(true, Ordering::Relaxed);
+        }
+        self.thread = None;
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.state.stopped.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+fn refresh(dir: &Path, path: &Path, owner: &str) -> std::io::Result<()> {
+    let _lock = lock_dir(dir)?;
+    match fs::read_to_string(path) {
+        Ok(content) if content == owner => fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now())),
+        Ok(_) => Err(ErrorKind::AlreadyExists.into()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Locks the lease directory until the returned file is dropped.
+fn lock_dir(dir: &Path) -> std::io::Result<fs::File> {
+    let file = fs::File::options()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(dir.join(".lock"))?;
+    file.lock()?;
+    Ok(file)
+}
+
+fn hostname() -> anyhow::Result<String> {
+    if let Ok(name) = std::env::var("HOSTNAME")
+        && !name.is_empty()
+    {
+        return Ok(name);
+    }
+    for path in ["/etc/hostname", "/proc/sys/kernel/hostname"] {
+        if let Ok(name) = fs::read_to_string(path)
+            && !name.trim().is_empty()
+        {
+            return Ok(name.trim().to_string());
+        }
+    }
+    anyhow::bail!("HostnameHash::allocate: hostname not found")
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is stable across Rust releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn t_lease_file() {
+        let dir = TempDir::new().unwrap();
+        let leases = LeaseFile::new(dir.path(), Duration::from_secs(60));
+
+        let first = leases.acquire(1).unwrap();
+        let second = leases.acquire(1).unwrap();
+        assert_eq!((first.node(), second.node()), (0, 1));
+        assert!(leases.acquire(1).is_err());
+
+        let stale = SystemTime::now() - Duration::from_secs(120);
+        let lease = fs::File::options()
+            .write(true)
+            .open(dir.path().join("node-1.lease"));
+        lease.unwrap().set_modified(stale).unwrap();
+        let taken = leases.acquire(1).unwrap();
+        assert_eq!(taken.node(), 1);
+
+        drop(second);
+        assert!(!first.is_lost());
+    }
+
+    #[test]
+    fn t_lease_lost() {
+        let dir = TempDir::new().unwrap();
+        let leases = LeaseFile::new(dir.path(), Duration::from_secs(60));
+
+        let lease = leases.acquire(1).unwrap();
+        assert!(lease.state.refresh());
+        assert!(!lease.is_lost());
+
+        fs::write(dir.path().join("node-0.lease"), "other 1 0").unwrap();
+        assert!(!lease.state.refresh());
+        assert!(lease.is_lost());
+    }
+}
@@ -0,0 +1,171 @@
+use std::{fmt, num::ParseIntError};
+
+/// The encoding of a `flake_id!` with a `prefix`. Both are fixed width, so
+/// ids sort as their strings do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdEncoding {
+    /// `0-9A-Za-z`, 11 characters.
+    Base62,
+    /// Crockford's base32, `0-9A-Z` without `I`, `L`, `O` and `U`, 13
+    /// characters.
+    Crockford,
+}
+
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const CROCKFORD: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+impl IdEncoding {
+    fn alphabet(&self) -> &'static [u8] {
+        match self {
+            IdEncoding::Base62 => BASE62,
+            IdEncoding::Crockford => CROCKFORD,
+        }
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            IdEncoding::Base62 => 11,
+            IdEncoding::Crockford => 13,
+        }
+    }
+
+    pub fn encode(&self, id: i64) -> String {
+        let alphabet = self.alphabet();
+        let base = alphabet.len() as u64;
+        let mut id = id as u64;
+        let mut out = vec![b'0'; self.width()];
+        for digit in out.iter_mut().rev() {
+            *digit = alphabet[(id % base) as usize];
+            id /= base;
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Accepts only the canonical form written by [`IdEncoding::encode`].
+    pub fn decode(&self, s: &str) -> Result<i64, String> {
+        if s.chars().count() != self.width() {
+            return Err(format!("expected {} characters", self.width()));
+        }
+        let alphabet = self.alphabet();
+        let base = alphabet.len() as u64;
+        let mut id = 0u64;
+        for c in s.chars() {
+            let Some(digit) = alphabet.iter().position(|a| char::from(*a) == c) else {
+                return Err(format!("invalid character `{c}`"));
+            };
+            id = id
+                .checked_mul(base)
+                .and_then(|id| id.checked_add(digit as u64))
+                .ok_or("out of range")?;
+        }
+        Ok(id as i64)
+    }
+}
+
+/// The error of parsing a `flake_id!` with a `prefix`.
+///
+/// Through `@serde`, it fails deserialization as `##<err_name>(<reason>)##`,
+/// the form mapped to `err_name` when decoding http requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdParseError {
+    pub type_name: &'static str,
+    pub reason: String,
+}
+
+impl IdParseError {
+    /// `Invalid<Type>`, e.g. `InvalidUserId`.
+    pub fn err_name(&self) -> String {
+        format!("Invalid{}", self.type_name)
+    }
+}
+
+impl fmt::Display for IdParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {}: {}", self.type_name, self.reason)
+    }
+}
+
+impl std::error::Error for IdParseError {}
+
+/// Checked by `flake_id!` at compile time: a prefix is non-empty and has no
+/// `_`, which separates it from the encoded id.
+#[doc(hidden)]
+pub const fn is_valid_prefix(prefix: &str) -> bool {
+    let bytes = prefix.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'_' {
+            return false;
+        }
+        i += 1;
+    }
+    !bytes.is_empty()
+}
+
+#[doc(hidden)]
+pub fn parse_prefixed(
+    type_name: &'static str,
+    prefix: &str,
+    encoding: IdEncoding,
+    s: &str,
+) -> Result<i64, IdParseError> {
+    let error = |reason: String| IdParseError { type_name, reason };
+    let encoded = s
+        .strip_prefix(prefix)
+        .and_then(|s| s.strip_prefix('_'))
+        .ok_or_else(|| error(format!("expected prefix `{prefix}_`")))?;
+    encoding.decode(encoded).map_err(error)
+}
+
+/// The message of an id that fails to deserialize.
+#[doc(hidden)]
+pub trait IdDeError {
+    fn de_message(&self) -> String;
+}
+
+impl IdDeError for ParseIntError {
+    fn de_message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl IdDeError for IdParseError {
+    /// Parentheses are dropped from the reason, since the http decoder ends
+    /// the message at the first one.
+    fn de_message(&self) -> String {
+        let reason = self.reason.replace(['(', ')'], "");
+        format!("##{}({})##", self.err_name(), reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_encodings() {
+        for encoding in [IdEncoding::Base62, IdEncoding::Crockford] {
+            let ids = [0, 1, 1_825_830_135_908_794_368, i64::MAX, -1];
+            let encoded: Vec<_> = ids.iter().map(|id| encoding.encode(*id)).collect();
+            for (id, s) in ids.iter().zip(&encoded) {
+                assert_eq!(encoding.decode(s), Ok(*id));
+            }
+            assert!(encoded.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+
+        assert_eq!(IdEncoding::Base62.encode(61), "0000000000z");
+        assert!(IdEncoding::Base62.decode("zzzzzzzzzzz").is_err());
+        assert!(IdEncoding::Crockford.decode("000000000000o").is_err());
+        assert_eq!(
+            IdEncoding::Base62.decode("0000000000é"),
+            Err("invalid character `é`".to_string())
+        );
+
+        let err = parse_prefixed("OrgId", "org", IdEncoding::Base62, "org_0000000000(");
+        assert_eq!(
+            err.unwrap_err().de_message(),
+            "##InvalidOrgId(invalid character ``)##"
+        );
+        assert!(is_valid_prefix("org") && !is_valid_prefix("") && !is_valid_prefix("my_org"));
+    }
+}
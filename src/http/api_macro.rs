@@ -1,9 +1,31 @@
+/// Creates the per-request [`ProviderContext`](crate::provider::ProviderContext)
+/// used by the generated handlers.
+///
+/// The context is scoped from the application container, so `Arc<T>`
+/// singletons of derived `Send + Sync` providers are built by the first
+/// request that needs them, or at startup, and reused by every later one.
+///
+/// The container is looked up as `web::Data<SyncProviderContext>` app data
+/// first, then as the
+/// [global](crate::provider::SyncProviderContext::set_global) container.
+/// Without either, a standalone context is returned.
+#[cfg(feature = "actix-web")]
+pub fn request_context(req: &actix_web::HttpRequest) -> crate::provider::ProviderContext {
+    use crate::provider::SyncProviderContext;
+
+    match req.app_data::<actix_web::web::Data<SyncProviderContext>>() {
+        Some(container) => container.clone().into_inner().scope(),
+        None => SyncProviderContext::global_scope(),
+    }
+}
+
 pub trait HttpTypeProvider {
     type Adapter;
     type UseCase;
 }
 
 #[macro_export]
+#[allow(clippy::crate_in_macro_def)]
 macro_rules! actix_api {
     ($name:ident) => {
         async fn $name(
@@ -13,7 +35,6 @@ macro_rules! actix_api {
             use lolibaso::http::HttpAdapter;
             use lolibaso::http::api_macro::HttpTypeProvider;
             use lolibaso::http::request::actix_impl::ActixHttpRequest;
-            use lolibaso::provider::Provider;
             use lolibaso::use_case::UseCase;
 
             use self::inject::$name;
@@ -22,17 +43,19 @@ macro_rules! actix_api {
             type __UseCase = <$name as HttpTypeProvider>::UseCase;
             type __Adapter = <$name as HttpTypeProvider>::Adapter;
 
+            // provide parser and use case from the application container
+            let (parser, use_case) = {
+                let mut ctx = lolibaso::http::api_macro::request_context(&req);
+                (ctx.build::<__Parser>()?, ctx.build::<__UseCase>()?)
+            };
+
             let req = ActixHttpRequest::new(req, payload);
 
             // get adapter
             let adapter = __Adapter::new();
 
             // convert input
-            let input =
-                HttpAdapter::<__UseCase, _>::convert_input(&adapter, &req, __Parser::provide()?)?;
-
-            // do use case
-            let use_case = __UseCase::provide()?;
+            let input = HttpAdapter::<__UseCase, _>::convert_input(&adapter, &req, parser)?;
 
             // execute use case
             let output = use_case.execute(input).await?;
@@ -64,6 +87,7 @@ pub trait WsTypeProvider {
 }
 
 #[macro_export]
+#[allow(clippy::crate_in_macro_def)]
 macro_rules! actix_ws_api {
     ($name:ident) => {
         async fn $name(
@@ -72,7 +96,6 @@ macro_rules! actix_ws_api {
         ) -> Result<actix_web::HttpResponse, lolibaso::http::error::HttpError> {
             use lolibaso::http::request::actix_impl::ActixHttpRequest;
             use lolibaso::http::web_socket::WSAdapter;
-            use lolibaso::provider::Provider;
             use lolibaso::http::api_macro::WsTypeProvider;
 
             use self::inject::$name;
@@ -84,6 +107,11 @@ macro_rules! actix_ws_api {
             type __Command = <$name as WsTypeProvider>::Command;
             type __Event = <$name as WsTypeProvider>::Event;
 
+            let (adapter, parser) = {
+                let mut ctx = lolibaso::http::api_macro::request_context(&req);
+                (ctx.build::<__Adapter>()?, ctx.build::<__Parser>()?)
+            };
+
             let req_clone = req.clone();
             let mut response = None;
             let get_ws = || {
@@ -99,8 +127,6 @@ macro_rules! actix_ws_api {
 
             let req = ActixHttpRequest::new(req, Bytes::new());
 
-            let adapter = __Adapter::provide()?;
-            let parser = __Parser::provide()?;
             WSAdapter::<__Parser, __Chan>::accept(adapter, &req, parser, get_ws)??;
 
             match response {
@@ -157,6 +183,13 @@ pub trait QueryProvider {
     ) -> crate::result::BizResult<Self::Response, super::error::BizError>;
 }
 
+/// Generates `$name::query`, an actix handler that builds the
+/// [`QueryRouter::Adapter`] from the [`request_context`] and runs its query.
+///
+/// Breaking change: `query` now takes the `HttpRequest` before the `Query`
+/// extractor, to find the application container. Routes registered with
+/// `web::get().to($name::query)` are unaffected; direct callers must pass the
+/// request as the first argument.
 #[macro_export]
 macro_rules! actix_query_api {
     ($name:ident) => {
@@ -168,11 +201,13 @@ macro_rules! actix_query_api {
 
             impl $name {
                 pub async fn query(
+                    req: actix_web::HttpRequest,
                     q: actix_web::web::Query<
                         <<$name as QueryRouter>::Adapter as QueryProvider>::Query,
                     >,
                 ) -> Result<actix_web::HttpResponse, lolibaso::http::error::HttpError> {
-                    let adapter = <$name as QueryRouter>::Adapter::provide()?;
+                    let adapter = lolibaso::http::api_macro::request_context(&req)
+                        .build::<<$name as QueryRouter>::Adapter>()?;
                     let resp = adapter.query(q.into_inner()).await??;
                     let response = SimpleQueryResponse { body: resp };
                     let response = ToActixResponse::to_actix_response(response);
@@ -55,14 +55,14 @@ where
     fn decode(&self, input: &'a [u8]) -> Result<T, DecodeError> {
         let res = serde_urlencoded::from_bytes::<T>(input);
         match res {
-            Ok(t) => return Ok(t),
+            Ok(t) => Ok(t),
             Err(err) => {
                 let s = err.to_string();
                 let Some((err_name, err_msg)) = extract_error_name(&s) else {
                     return Err(DecodeError::BizErr(BizError::InvalidQuery.with_context(s)));
                 };
 
-                return Err(DecodeError::Custom { err_name, err_msg });
+                Err(DecodeError::Custom { err_name, err_msg })
             }
         }
     }
@@ -13,8 +13,17 @@ pub mod result;
 pub mod runtime;
 
 pub mod use_case;
+#[cfg(any(feature = "uuid", feature = "ulid"))]
+pub mod uuid_id;
 
 pub use lolibaso_macros::*;
 
 pub use derive_more;
 pub use flaken;
+#[cfg(feature = "schema")]
+pub use schemars;
+#[cfg(feature = "ulid")]
+pub use ulid;
+#[cfg(feature = "uuid")]
+pub use uuid;
+pub use zeroize;
@@ -1,66 +1,403 @@
 use std::{
     any::{Any, TypeId},
+    cell::RefCell,
     collections::HashMap,
     marker::PhantomData,
-    rc::Rc,
+    rc::{Rc, Weak},
     sync::Arc,
 };
 
+mod async_provider;
+mod config;
+pub mod graph;
+mod lazy;
+mod lifecycle;
+pub mod select;
+mod sync_context;
+
+pub use async_provider::AsyncProvider;
+pub use lazy::{Factory, Lazy};
+pub use lifecycle::{Dispose, ShutdownError};
+#[doc(hidden)]
+pub use select::{select_variant, variant_when};
+pub use sync_context::SyncProviderContext;
+#[doc(hidden)]
+pub use sync_context::share;
+
+use graph::{ProviderNode, Shared};
+
 pub trait Provider: Sized + 'static {
     fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self>;
 
     #[allow(dead_code)]
     fn provide() -> anyhow::Result<Self> {
         let mut ctx = ProviderContext::new();
-        Self::build(&mut ctx)
+        ctx.build()
     }
 
     fn provide_with(f: impl FnOnce(&mut ProviderContext)) -> anyhow::Result<Self> {
         let mut ctx = ProviderContext::new();
         f(&mut ctx);
-        Self::build(&mut ctx)
+        ctx.build()
+    }
+
+    /// Describes the dependencies of this provider. See [`graph`].
+    fn node() -> ProviderNode {
+        ProviderNode::leaf::<Self>()
+    }
+
+    /// Called to build `Arc<Self>` with `build` in a scope of `container`.
+    /// By default it is built and cached in the scope only.
+    ///
+    /// Derived providers of `Send + Sync` types go through
+    /// [`SyncProviderContext::get_or_build`], so that every scope shares one
+    /// instance and concurrent scopes build it once. Hand-written providers
+    /// opt in with `container.get_or_build(build)`. Derived providers of
+    /// generic structs keep the default, since the derive can not tell
+    /// whether every instantiation is `Send + Sync`.
+    fn share_arc(
+        container: &SyncProviderContext,
+        build: &mut dyn FnMut() -> anyhow::Result<Arc<Self>>,
+    ) -> anyhow::Result<Arc<Self>> {
+        let _ = container;
+        build()
+    }
+
+    /// Like [`Provider::share_arc`], for [`SingletonProvider::build_single`].
+    fn share_singleton(
+        container: &SyncProviderContext,
+        build: &mut dyn FnMut() -> anyhow::Result<Self>,
+    ) -> anyhow::Result<Self> {
+        let _ = container;
+        build()
     }
 }
 
 pub trait SingletonProvider: Provider + Clone + 'static {
     fn build_single(ctx: &mut crate::provider::ProviderContext) -> anyhow::Result<Self> {
-        if let Some(this) = ctx.get::<Self>() {
-            return Ok(this.clone());
+        if let Some(this) = ctx.get_cloned::<Self>() {
+            return Ok(this);
         }
 
-        let this = Self::build(ctx)?;
+        let this = match ctx.shared().cloned() {
+            Some(shared) => Self::share_singleton(&shared, &mut || ctx.build::<Self>())?,
+            None => ctx.build::<Self>()?,
+        };
         ctx.insert(this.clone());
         Ok(this)
     }
 }
 
 pub struct ProviderContext {
-    map: HashMap<TypeId, Box<dyn Any>>,
+    map: HashMap<Key, Rc<dyn Any>>,
+    /// Types currently being built, outermost first.
+    stack: Vec<BuildFrame>,
+    /// Read-only fallback for lookups that miss `map`.
+    parent: Option<Rc<ProviderContext>>,
+    /// Thread-safe fallback consulted by [`ProviderContext::get_cloned`].
+    shared: Option<Arc<SyncProviderContext>>,
+    /// Factories that replace the regular construction of a type.
+    overrides: HashMap<Key, OverrideFn>,
+    /// Implementations bound to unsized types, keyed by the unsized type.
+    /// Each value is a `BindingFn<T>`.
+    bindings: HashMap<TypeId, Rc<dyn Any>>,
+    /// Cleanup hooks in build order.
+    disposers: Rc<RefCell<Vec<lifecycle::Disposer>>>,
+    /// Where the cleanup hooks left on drop go, for forks.
+    dispose_to: Option<Rc<RefCell<Vec<lifecycle::Disposer>>>>,
+    /// The instances of the context this one was forked from by
+    /// [`ProviderContext::fork_shared`].
+    forked: Option<Rc<ForkedInstances>>,
+    /// The snapshots of `map` handed to forks.
+    forks: Vec<Weak<ForkedInstances>>,
+}
+
+/// The instances of a context as seen by its forks. They are referenced
+/// weakly while the context lives, so that it can still take them, and kept
+/// alive once it is dropped.
+#[derive(Default)]
+struct ForkedInstances {
+    entries: HashMap<Key, Weak<dyn Any>>,
+    kept: RefCell<Vec<Rc<dyn Any>>>,
+}
+
+type OverrideFn = Rc<dyn Fn(&mut ProviderContext) -> anyhow::Result<Box<dyn Any>>>;
+
+type BindingFn<T> = Rc<dyn Fn(&mut ProviderContext) -> anyhow::Result<Box<T>>>;
+
+/// Storage key of an instance: its type plus an optional name or tag, so
+/// several instances of one type can live side by side.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    type_id: TypeId,
+    qualifier: Qualifier,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Qualifier {
+    None,
+    Name(&'static str),
+    /// A marker type, see [`ProviderContext::insert_tagged`].
+    Tag(TypeId),
+}
+
+impl Key {
+    fn of<T: 'static>() -> Self {
+        Key {
+            type_id: TypeId::of::<T>(),
+            qualifier: Qualifier::None,
+        }
+    }
+
+    fn named<T: 'static>(name: &'static str) -> Self {
+        Key {
+            type_id: TypeId::of::<T>(),
+            qualifier: Qualifier::Name(name),
+        }
+    }
+
+    fn tagged<Tag: 'static, T: 'static>() -> Self {
+        Key {
+            type_id: TypeId::of::<T>(),
+            qualifier: Qualifier::Tag(TypeId::of::<Tag>()),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct BuildFrame {
+    type_id: TypeId,
+    type_name: &'static str,
+}
+
+/// Context attached to errors returned from [`ProviderContext::build`],
+/// holding the chain of types that were being built when the error occurred.
+#[derive(Debug, Clone)]
+pub struct BuildPath(Vec<&'static str>);
+
+impl BuildPath {
+    pub fn types(&self) -> &[&'static str] {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for BuildPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Provider::build: path = ")?;
+        write_path(f, self.0.iter().copied())
+    }
+}
+
+impl Default for ProviderContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProviderContext {
     pub fn new() -> Self {
+        Self::with_fallbacks(None, None)
+    }
+
+    fn with_fallbacks(
+        parent: Option<Rc<ProviderContext>>,
+        shared: Option<Arc<SyncProviderContext>>,
+    ) -> Self {
         ProviderContext {
             map: HashMap::new(),
+            stack: Vec::new(),
+            parent,
+            shared,
+            overrides: HashMap::new(),
+            bindings: HashMap::new(),
+            disposers: Rc::default(),
+            dispose_to: None,
+            forked: None,
+            forks: Vec::new(),
         }
     }
 
+    /// Creates a scoped context that falls back to `parent` for lookups.
+    ///
+    /// Instances inserted into the child shadow the parent's and are dropped
+    /// with the child. The parent is never modified, so shared singletons such
+    /// as `Arc<T>` should be built into it before it is shared.
+    pub fn child(parent: Rc<ProviderContext>) -> Self {
+        Self::with_fallbacks(Some(parent), None)
+    }
+
+    /// Creates an empty context that shares this one's parents, shared
+    /// context, overrides and bindings. Instances held directly by this
+    /// context are not carried over.
+    ///
+    /// Instances the fork registers for disposal and still holds when it is
+    /// dropped are handed back to this context's [`shutdown`](Self::shutdown).
+    pub fn fork(&self) -> Self {
+        let parent = match &self.forked {
+            Some(forked) => Some(Rc::new(forked.to_context(self.parent.clone()))),
+            None => self.parent.clone(),
+        };
+        let mut fork = Self::with_fallbacks(parent, self.shared.clone());
+        fork.overrides = self.overrides.clone();
+        fork.bindings = self.bindings.clone();
+        fork.dispose_to = Some(
+            self.dispose_to
+                .clone()
+                .unwrap_or_else(|| self.disposers.clone()),
+        );
+        fork
+    }
+
+    /// Like [`ProviderContext::fork`], but the forks of the returned context
+    /// also see the instances held by this one.
+    ///
+    /// The instances stay in this context and can still be taken with
+    /// [`ProviderContext::remove`], after which forks no longer see them.
+    /// Those left when this context is dropped are kept for its forks.
+    pub fn fork_shared(&mut self) -> Self {
+        let forked = Rc::new(ForkedInstances {
+            entries: self
+                .map
+                .iter()
+                .map(|(key, val)| (*key, Rc::downgrade(val)))
+                .collect(),
+            kept: RefCell::default(),
+        });
+        self.forks.push(Rc::downgrade(&forked));
+        let mut fork = self.fork();
+        fork.forked = Some(forked);
+        fork
+    }
+
+    /// Hands the instances still referenced by forks over to them.
+    fn keep_forked_instances(&mut self) {
+        for forked in self.forks.drain(..).filter_map(|f| f.upgrade()) {
+            let kept = forked.entries.values().filter_map(Weak::upgrade);
+            forked.kept.borrow_mut().extend(kept);
+        }
+    }
+
+    pub fn parent(&self) -> Option<&Rc<ProviderContext>> {
+        self.parent.as_ref()
+    }
+
+    /// Looks up `T` in this context, then in its parents.
     pub fn get<T: 'static>(&self) -> Option<&T> {
-        self.map
-            .get(&TypeId::of::<T>())
-            .and_then(|boxed| boxed.downcast_ref())
+        self.get_by_key(Key::of::<T>())
+    }
+
+    /// Like [`ProviderContext::get`], but also falls back to the
+    /// [`SyncProviderContext`] this context was scoped from.
+    pub fn get_cloned<T: Clone + 'static>(&self) -> Option<T> {
+        self.get_cloned_by_key(Key::of::<T>())
+    }
+
+    pub fn shared(&self) -> Option<&Arc<SyncProviderContext>> {
+        self.shared.as_ref()
+    }
+
+    /// Looks up `T` in this context only, ignoring parents.
+    pub fn get_local<T: 'static>(&self) -> Option<&T> {
+        self.get_local_by_key(Key::of::<T>())
     }
 
+    /// Removes `T` from this context. Instances owned by a parent are shared
+    /// and can not be taken.
     pub fn remove<T: 'static>(&mut self) -> Option<T> {
-        self.map
-            .remove(&TypeId::of::<T>())
-            .and_then(|boxed| downcast_owned(boxed))
+        self.remove_by_key(Key::of::<T>())
     }
 
     pub fn insert<T: 'static>(&mut self, val: T) -> Option<T> {
-        self.map
-            .insert(TypeId::of::<T>(), Box::new(val))
-            .and_then(downcast_owned)
+        self.insert_by_key(Key::of::<T>(), val)
+    }
+
+    /// Looks up the instance of `T` registered under `name`, in this context
+    /// and then in its parents. Named instances are independent of the
+    /// unnamed instance of the same type.
+    pub fn get_named<T: 'static>(&self, name: &'static str) -> Option<&T> {
+        self.get_by_key(Key::named::<T>(name))
+    }
+
+    /// Like [`ProviderContext::get_named`], but also falls back to the
+    /// [`SyncProviderContext`] this context was scoped from.
+    pub fn get_cloned_named<T: Clone + 'static>(&self, name: &'static str) -> Option<T> {
+        self.get_cloned_by_key(Key::named::<T>(name))
+    }
+
+    pub fn remove_named<T: 'static>(&mut self, name: &'static str) -> Option<T> {
+        self.remove_by_key(Key::named::<T>(name))
+    }
+
+    pub fn insert_named<T: 'static>(&mut self, name: &'static str, val: T) -> Option<T> {
+        self.insert_by_key(Key::named::<T>(name), val)
+    }
+
+    pub fn with_named_instance<I: 'static>(mut self, name: &'static str, instance: I) -> Self {
+        self.insert_named(name, instance);
+        self
+    }
+
+    /// Looks up the instance of `T` registered under the marker type `Tag`,
+    /// e.g. `ctx.get_tagged::<Replica, DbPool>()`. Like names, tags are
+    /// independent of the unnamed instance, and a typo is a compile error.
+    pub fn get_tagged<Tag: 'static, T: 'static>(&self) -> Option<&T> {
+        self.get_by_key(Key::tagged::<Tag, T>())
+    }
+
+    pub fn get_cloned_tagged<Tag: 'static, T: Clone + 'static>(&self) -> Option<T> {
+        self.get_cloned_by_key(Key::tagged::<Tag, T>())
+    }
+
+    pub fn remove_tagged<Tag: 'static, T: 'static>(&mut self) -> Option<T> {
+        self.remove_by_key(Key::tagged::<Tag, T>())
+    }
+
+    pub fn insert_tagged<Tag: 'static, T: 'static>(&mut self, val: T) -> Option<T> {
+        self.insert_by_key(Key::tagged::<Tag, T>(), val)
+    }
+
+    pub fn with_tagged_instance<Tag: 'static, I: 'static>(mut self, instance: I) -> Self {
+        self.insert_tagged::<Tag, I>(instance);
+        self
+    }
+
+    fn get_by_key<T: 'static>(&self, key: Key) -> Option<&T> {
+        self.get_local_by_key(key)
+            .or_else(|| self.parent.as_ref().and_then(|p| p.get_by_key(key)))
+    }
+
+    fn get_cloned_by_key<T: Clone + 'static>(&self, key: Key) -> Option<T> {
+        if let Some(val) = self.get_local_by_key::<T>(key) {
+            return Some(val.clone());
+        }
+        if let Some(val) = self.parent.as_ref().and_then(|p| p.get_cloned_by_key(key)) {
+            return Some(val);
+        }
+        self.shared.as_ref().and_then(|s| s.get_by_key(key))
+    }
+
+    fn get_local_by_key<T: 'static>(&self, key: Key) -> Option<&T> {
+        self.map.get(&key).and_then(|val| val.downcast_ref())
+    }
+
+    /// Fails while a fork is being built with the instance.
+    fn remove_by_key<T: 'static>(&mut self, key: Key) -> Option<T> {
+        let val = self.map.remove(&key)?;
+        match val.downcast::<T>().map(Rc::try_unwrap) {
+            Ok(Ok(val)) => Some(val),
+            Ok(Err(val)) => {
+                self.map.insert(key, val);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    fn insert_by_key<T: 'static>(&mut self, key: Key, val: T) -> Option<T> {
+        let old = self.map.insert(key, Rc::new(val))?;
+        old.downcast::<T>()
+            .ok()
+            .and_then(|old| Rc::try_unwrap(old).ok())
     }
 
     pub fn with_instance<I: 'static>(mut self, instance: I) -> Self {
@@ -68,11 +405,287 @@ impl ProviderContext {
         self
     }
 
+    /// Registers a factory that is used instead of the regular construction
+    /// of `T`, wherever `T` appears in the dependency graph.
+    ///
+    /// Overrides apply to every unqualified field kind of `#[derive(Provider)]`
+    /// and are inherited by child contexts. Named and tagged fields only see
+    /// overrides registered for the same qualifier. Mainly meant for swapping
+    /// in fakes in tests.
+    pub fn override_with<T, F>(&mut self, factory: F)
+    where
+        T: 'static,
+        F: Fn(&mut ProviderContext) -> anyhow::Result<T> + 'static,
+    {
+        self.override_by_key(Key::of::<T>(), factory);
+    }
+
+    /// Overrides `T` with clones of `val`.
+    pub fn override_instance<T: Clone + 'static>(&mut self, val: T) {
+        self.override_with(move |_| Ok(val.clone()));
+    }
+
+    /// Like [`ProviderContext::override_with`], for fields named `name`.
+    pub fn override_named_with<T, F>(&mut self, name: &'static str, factory: F)
+    where
+        T: 'static,
+        F: Fn(&mut ProviderContext) -> anyhow::Result<T> + 'static,
+    {
+        self.override_by_key(Key::named::<T>(name), factory);
+    }
+
+    pub fn override_named_instance<T: Clone + 'static>(&mut self, name: &'static str, val: T) {
+        self.override_named_with(name, move |_| Ok(val.clone()));
+    }
+
+    /// Like [`ProviderContext::override_with`], for fields tagged with `Tag`.
+    pub fn override_tagged_with<Tag, T, F>(&mut self, factory: F)
+    where
+        Tag: 'static,
+        T: 'static,
+        F: Fn(&mut ProviderContext) -> anyhow::Result<T> + 'static,
+    {
+        self.override_by_key(Key::tagged::<Tag, T>(), factory);
+    }
+
+    pub fn override_tagged_instance<Tag: 'static, T: Clone + 'static>(&mut self, val: T) {
+        self.override_tagged_with::<Tag, _, _>(move |_| Ok(val.clone()));
+    }
+
+    /// Builds `T` from its override, if one is registered here or in a parent.
+    pub fn build_override<T: 'static>(&mut self) -> Option<anyhow::Result<T>> {
+        self.build_override_by_key(Key::of::<T>())
+    }
+
+    pub fn build_named_override<T: 'static>(
+        &mut self,
+        name: &'static str,
+    ) -> Option<anyhow::Result<T>> {
+        self.build_override_by_key(Key::named::<T>(name))
+    }
+
+    pub fn build_tagged_override<Tag: 'static, T: 'static>(&mut self) -> Option<anyhow::Result<T>> {
+        self.build_override_by_key(Key::tagged::<Tag, T>())
+    }
+
+    fn override_by_key<T, F>(&mut self, key: Key, factory: F)
+    where
+        T: 'static,
+        F: Fn(&mut ProviderContext) -> anyhow::Result<T> + 'static,
+    {
+        let factory: OverrideFn =
+            Rc::new(move |ctx| factory(ctx).map(|val| Box::new(val) as Box<dyn Any>));
+        self.overrides.insert(key, factory);
+    }
+
+    fn build_override_by_key<T: 'static>(&mut self, key: Key) -> Option<anyhow::Result<T>> {
+        let factory = self.find_override(key)?;
+        let res = factory(self).map(|boxed| {
+            downcast_owned(boxed).expect("override factory returns the overridden type")
+        });
+        Some(res)
+    }
+
+    fn find_override(&self, key: Key) -> Option<OverrideFn> {
+        match self.overrides.get(&key) {
+            Some(factory) => Some(factory.clone()),
+            None => self.parent.as_ref()?.find_override(key),
+        }
+    }
+
+    /// Binds the trait object `T` to the provider `I`, so that `Box<T>`,
+    /// `Arc<T>` and `Rc<T>` fields of derived providers are built from `I`.
+    ///
+    /// `coerce` performs the unsizing, which can not be written generically;
+    /// a plain `|b| b` is enough:
+    ///
+    /// ```ignore
+    /// ctx.bind::<dyn Repository<User>, PgUserRepo>(|b| b);
+    /// ```
+    pub fn bind<T, I>(&mut self, coerce: fn(Box<I>) -> Box<T>)
+    where
+        T: ?Sized + 'static,
+        I: Provider,
+    {
+        self.bind_with(move |ctx| Ok(coerce(Box::new(ctx.build::<I>()?))));
+    }
+
+    /// Binds the trait object `T` to a factory.
+    pub fn bind_with<T, F>(&mut self, factory: F)
+    where
+        T: ?Sized + 'static,
+        F: Fn(&mut ProviderContext) -> anyhow::Result<Box<T>> + 'static,
+    {
+        let factory: BindingFn<T> = Rc::new(factory);
+        self.bindings.insert(TypeId::of::<T>(), Rc::new(factory));
+    }
+
+    fn find_binding<T: ?Sized + 'static>(&self) -> Option<BindingFn<T>> {
+        match self.bindings.get(&TypeId::of::<T>()) {
+            Some(factory) => factory.downcast_ref::<BindingFn<T>>().cloned(),
+            None => self.parent.as_ref()?.find_binding(),
+        }
+    }
+
+    /// Builds a fresh `Box<T>` from the implementation bound to `T`.
+    pub fn build_boxed<T: ?Sized + 'static>(&mut self) -> anyhow::Result<Box<T>> {
+        let frame = self.enter::<Box<T>>()?;
+        let res = match self.build_override::<Box<T>>() {
+            Some(res) => res,
+            None => match self.find_binding::<T>() {
+                Some(factory) => factory(self),
+                None => Err(anyhow::anyhow!(
+                    "Provider::build: no implementation bound. type = {}",
+                    short_type_name(std::any::type_name::<T>())
+                )),
+            },
+        };
+        self.exit(frame, res)
+    }
+
+    /// Builds `Arc<T>` from the implementation bound to `T`, caching it in
+    /// the context like `Arc<T>` of a sized provider.
+    pub fn build_arc<T: ?Sized + 'static>(&mut self) -> anyhow::Result<Arc<T>> {
+        if let Some(this) = self.get_cloned::<Arc<T>>() {
+            return Ok(this);
+        }
+        let frame = self.enter::<Arc<T>>()?;
+        let res = match self.build_override::<Arc<T>>() {
+            Some(res) => res,
+            None => self.build_boxed::<T>().map(Arc::from),
+        };
+        let this = self.exit(frame, res)?;
+        self.insert(this.clone());
+        Ok(this)
+    }
+
+    /// Builds `Rc<T>` from the implementation bound to `T`, caching it in
+    /// the context like `Rc<T>` of a sized provider.
+    pub fn build_rc<T: ?Sized + 'static>(&mut self) -> anyhow::Result<Rc<T>> {
+        if let Some(this) = self.get_cloned::<Rc<T>>() {
+            return Ok(this);
+        }
+        let frame = self.enter::<Rc<T>>()?;
+        let res = match self.build_override::<Rc<T>>() {
+            Some(res) => res,
+            None => self.build_boxed::<T>().map(Rc::from),
+        };
+        let this = self.exit(frame, res)?;
+        self.insert(this.clone());
+        Ok(this)
+    }
+
+    /// Builds `T`, tracking it on the in-progress build stack.
+    ///
+    /// Fails if `T` is already being built further up the stack, and annotates
+    /// any error with the [`BuildPath`] that led to it.
     pub fn build<T>(&mut self) -> anyhow::Result<T>
     where
         T: Provider,
     {
-        Provider::build(self)
+        let frame = self.enter::<T>()?;
+        let res = match self.build_override::<T>() {
+            Some(res) => res,
+            None => T::build(self),
+        };
+        self.exit(frame, res)
+    }
+
+    /// Pushes `T` onto the build stack, failing if it is already there.
+    fn enter<T: ?Sized + 'static>(&mut self) -> anyhow::Result<BuildFrame> {
+        let frame = BuildFrame {
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+        };
+
+        if let Some(pos) = self.stack.iter().position(|f| f.type_id == frame.type_id) {
+            let mut chain = String::new();
+            let names = self.stack[pos..]
+                .iter()
+                .chain(Some(&frame))
+                .map(|f| f.type_name);
+            write_path(&mut chain, names).unwrap();
+            let err = anyhow::anyhow!("Provider::build: dependency cycle detected. {chain}");
+            return Err(err.context(self.build_path_with(frame)));
+        }
+
+        self.stack.push(frame);
+        Ok(frame)
+    }
+
+    /// Pops `frame` and annotates the error, if any, with the build path.
+    fn exit<T>(&mut self, frame: BuildFrame, res: anyhow::Result<T>) -> anyhow::Result<T> {
+        self.stack.pop();
+
+        res.map_err(|err| {
+            if err.downcast_ref::<BuildPath>().is_some() {
+                err
+            } else {
+                err.context(self.build_path_with(frame))
+            }
+        })
+    }
+
+    /// Types currently being built, outermost first.
+    pub fn build_path(&self) -> BuildPath {
+        BuildPath(self.stack.iter().map(|f| f.type_name).collect())
+    }
+
+    fn build_path_with(&self, frame: BuildFrame) -> BuildPath {
+        let mut path = self.build_path();
+        path.0.push(frame.type_name);
+        path
+    }
+}
+
+fn write_path<'a>(
+    w: &mut impl std::fmt::Write,
+    names: impl Iterator<Item = &'a str>,
+) -> std::fmt::Result {
+    for (idx, name) in names.enumerate() {
+        if idx > 0 {
+            w.write_str(" -> ")?;
+        }
+        write_short_type_name(w, name)?;
+    }
+    Ok(())
+}
+
+/// Writes `name` with module paths stripped, e.g.
+/// `alloc::sync::Arc<app::UserRepo>` becomes `Arc<UserRepo>`.
+fn write_short_type_name(w: &mut impl std::fmt::Write, name: &str) -> std::fmt::Result {
+    let mut segment_start = 0;
+    for (idx, ch) in name.char_indices() {
+        if ch.is_alphanumeric() || ch == '_' || ch == ':' {
+            continue;
+        }
+        write_last_segment(w, &name[segment_start..idx])?;
+        w.write_char(ch)?;
+        segment_start = idx + ch.len_utf8();
+    }
+    write_last_segment(w, &name[segment_start..])
+}
+
+fn short_type_name(name: &str) -> String {
+    let mut short = String::new();
+    write_short_type_name(&mut short, name).unwrap();
+    short
+}
+
+fn write_last_segment(w: &mut impl std::fmt::Write, path: &str) -> std::fmt::Result {
+    w.write_str(path.rsplit("::").next().unwrap_or(path))
+}
+
+impl ForkedInstances {
+    /// A parent holding the instances that are still alive, for one fork.
+    fn to_context(&self, parent: Option<Rc<ProviderContext>>) -> ProviderContext {
+        let mut ctx = ProviderContext::with_fallbacks(parent, None);
+        ctx.map = self
+            .entries
+            .iter()
+            .filter_map(|(key, val)| Some((*key, val.upgrade()?)))
+            .collect();
+        ctx
     }
 }
 
@@ -100,16 +713,22 @@ where
     T: Provider,
 {
     fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
-        if let Some(t) = ctx.get::<Self>() {
-            return Ok(t.clone());
+        if let Some(t) = ctx.get_cloned::<Self>() {
+            return Ok(t);
         }
 
-        let t = T::build(ctx)?;
-        let this = Arc::new(t);
+        let this = match ctx.shared().cloned() {
+            Some(shared) => T::share_arc(&shared, &mut || ctx.build::<T>().map(Arc::new))?,
+            None => Arc::new(ctx.build::<T>()?),
+        };
         ctx.insert(this.clone());
 
         Ok(this)
     }
+
+    fn node() -> ProviderNode {
+        ProviderNode::shared::<Self>(T::node(), Shared::Arc)
+    }
 }
 
 impl<T> Provider for Rc<T>
@@ -117,14 +736,194 @@ where
     T: Provider,
 {
     fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
-        if let Some(t) = ctx.get::<Self>() {
-            return Ok(t.clone());
+        if let Some(t) = ctx.get_cloned::<Self>() {
+            return Ok(t);
         }
 
-        let t = T::build(ctx)?;
+        let t = ctx.build::<T>()?;
         let this = Rc::new(t);
         ctx.insert(this.clone());
 
         Ok(this)
     }
+
+    fn node() -> ProviderNode {
+        ProviderNode::shared::<Self>(T::node(), Shared::Rc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct UserService;
+    struct UserRepo;
+    struct Cache;
+
+    impl Provider for UserService {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            ctx.build::<Arc<UserRepo>>()?;
+            Ok(UserService)
+        }
+    }
+
+    impl Provider for UserRepo {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            ctx.build::<Cache>()?;
+            Ok(UserRepo)
+        }
+    }
+
+    impl Provider for Cache {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            if ctx.get::<bool>().is_some() {
+                ctx.build::<UserService>()?;
+            }
+            anyhow::ensure!(ctx.get::<u32>().is_some(), "cache size not found");
+            Ok(Cache)
+        }
+    }
+
+    #[test]
+    fn t_cycle_detected() {
+        let err = UserService::provide_with(|ctx| {
+            ctx.insert(true);
+        })
+        .err()
+        .unwrap();
+
+        let msg = format!("{err:#}");
+        assert!(
+            msg.contains("UserService -> Arc<UserRepo> -> UserRepo -> Cache -> UserService"),
+            "{msg}"
+        );
+    }
+
+    #[test]
+    fn t_error_annotated_with_path() {
+        let err = UserService::provide().err().unwrap();
+
+        let path = err.downcast_ref::<BuildPath>().unwrap();
+        assert_eq!(path.types().len(), 4);
+        assert_eq!(
+            err.to_string(),
+            "Provider::build: path = UserService -> Arc<UserRepo> -> UserRepo -> Cache"
+        );
+        assert_eq!(err.root_cause().to_string(), "cache size not found");
+    }
+
+    #[test]
+    fn t_build_ok() {
+        let mut ctx = ProviderContext::new().with_instance(8u32);
+        ctx.build::<UserService>().unwrap();
+        assert!(ctx.get::<Arc<UserRepo>>().is_some());
+        assert!(ctx.build_path().types().is_empty());
+    }
+
+    #[test]
+    fn t_override_deep_dependency() {
+        let service = UserService::provide_with(|ctx| {
+            ctx.override_with(|_| Ok(Cache));
+        });
+        assert!(service.is_ok());
+
+        let mut ctx = ProviderContext::new();
+        ctx.override_instance(Size(3));
+        ctx.override_with::<UserRepo, _>(|ctx| {
+            anyhow::ensure!(ctx.build::<Size>()?.0 == 3);
+            Ok(UserRepo)
+        });
+        let mut child = ProviderContext::child(Rc::new(ctx));
+        assert!(child.build::<UserService>().is_ok());
+        assert!(child.get_local::<Arc<UserRepo>>().is_some());
+    }
+
+    #[test]
+    fn t_named_instances() {
+        let shared = Arc::new(SyncProviderContext::new());
+        shared.insert_named("replica", Size(2));
+
+        let mut ctx = shared.scope().with_named_instance("primary", Size(1));
+        ctx.insert(Size(0));
+        assert_eq!(ctx.get::<Size>().unwrap().0, 0);
+        assert_eq!(ctx.get_named::<Size>("primary").unwrap().0, 1);
+        assert!(ctx.get_named::<Size>("replica").is_none());
+        assert_eq!(ctx.get_cloned_named::<Size>("replica").unwrap().0, 2);
+
+        assert_eq!(ctx.remove_named::<Size>("primary").unwrap().0, 1);
+        assert!(ctx.get_named::<Size>("primary").is_none());
+        assert_eq!(ctx.get::<Size>().unwrap().0, 0);
+    }
+
+    trait Store {
+        fn size(&self) -> u32;
+    }
+
+    impl Store for Size {
+        fn size(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn t_bind_trait_object() {
+        let mut ctx = ProviderContext::new();
+        let err = ctx.build_boxed::<dyn Store>().err().unwrap();
+        assert_eq!(
+            err.root_cause().to_string(),
+            "Provider::build: no implementation bound. type = dyn Store"
+        );
+
+        ctx.override_instance(Size(4));
+        ctx.bind::<dyn Store, Size>(|b| b);
+        assert_eq!(ctx.build_boxed::<dyn Store>().unwrap().size(), 4);
+
+        let arc = ctx.build_arc::<dyn Store>().unwrap();
+        assert!(Arc::ptr_eq(&arc, &ctx.build_arc::<dyn Store>().unwrap()));
+
+        let mut child = ProviderContext::child(Rc::new(ctx));
+        assert_eq!(child.build_rc::<dyn Store>().unwrap().size(), 4);
+    }
+
+    #[derive(Clone)]
+    struct Size(u32);
+
+    impl Provider for Size {
+        fn build(_ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            anyhow::bail!("not overridden")
+        }
+    }
+
+    struct Counter;
+
+    impl Provider for Counter {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            let count = ctx.remove::<usize>().unwrap_or_default();
+            ctx.insert(count + 1);
+            Ok(Counter)
+        }
+    }
+
+    #[test]
+    fn t_child_falls_back_to_parent() {
+        let mut app = ProviderContext::new().with_instance("app");
+        let shared = app.build::<Arc<Counter>>().unwrap();
+        let app = Rc::new(app);
+
+        let mut req = ProviderContext::child(app.clone()).with_instance("req");
+        assert_eq!(req.get::<&str>(), Some(&"req"));
+        assert!(req.get_local::<Arc<Counter>>().is_none());
+
+        let built = req.build::<Arc<Counter>>().unwrap();
+        assert!(Arc::ptr_eq(&shared, &built));
+        assert!(req.get_local::<usize>().is_none());
+
+        req.build::<Rc<Counter>>().unwrap();
+        assert_eq!(req.get_local::<usize>(), Some(&1));
+        drop(req);
+
+        assert_eq!(app.get::<&str>(), Some(&"app"));
+        assert!(app.get::<Rc<Counter>>().is_none());
+        assert_eq!(Rc::strong_count(&app), 1);
+    }
 }
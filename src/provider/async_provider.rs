@@ -0,0 +1,148 @@
+use std::{pin::Pin, rc::Rc, sync::Arc};
+
+use super::{
+    ProviderContext, SyncProviderContext,
+    graph::{ProviderNode, Shared},
+};
+
+/// Like [`Provider`](super::Provider), but for types that need I/O to construct, such as
+/// database pools or HTTP clients that connect at startup.
+pub trait AsyncProvider: Sized + 'static {
+    async fn build_async(ctx: &mut ProviderContext) -> anyhow::Result<Self>;
+
+    async fn provide_async() -> anyhow::Result<Self> {
+        let mut ctx = ProviderContext::new();
+        ctx.build_async().await
+    }
+
+    async fn provide_async_with(f: impl FnOnce(&mut ProviderContext)) -> anyhow::Result<Self> {
+        let mut ctx = ProviderContext::new();
+        f(&mut ctx);
+        ctx.build_async().await
+    }
+
+    fn node() -> ProviderNode {
+        ProviderNode::leaf::<Self>()
+    }
+
+    /// Called when `Arc<Self>` was built in a scope of `container`, returning
+    /// the instance to use. Derived providers of `Send + Sync` types store it
+    /// in the container, or return the one stored there first. Unlike
+    /// [`Provider::share_arc`](super::Provider::share_arc), concurrent scopes
+    /// may each build it before one is kept.
+    fn share_arc(this: Arc<Self>, container: &SyncProviderContext) -> Arc<Self> {
+        let _ = container;
+        this
+    }
+}
+
+impl ProviderContext {
+    /// Async counterpart of [`ProviderContext::build`], with the same cycle
+    /// detection and error annotation.
+    pub async fn build_async<T>(&mut self) -> anyhow::Result<T>
+    where
+        T: AsyncProvider,
+    {
+        let frame = self.enter::<T>()?;
+        let res = match self.build_override::<T>() {
+            Some(res) => res,
+            None => {
+                // Boxed so that dependency cycles are reported at runtime instead
+                // of producing an infinitely sized future.
+                let fut: Pin<Box<dyn Future<Output = anyhow::Result<T>> + '_>> =
+                    Box::pin(T::build_async(self));
+                fut.await
+            }
+        };
+        self.exit(frame, res)
+    }
+}
+
+impl<T> AsyncProvider for Arc<T>
+where
+    T: AsyncProvider,
+{
+    async fn build_async(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+        if let Some(t) = ctx.get_cloned::<Self>() {
+            return Ok(t);
+        }
+
+        let t = ctx.build_async::<T>().await?;
+        let mut this = Arc::new(t);
+        if let Some(shared) = ctx.shared().cloned() {
+            this = T::share_arc(this, &shared);
+        }
+        ctx.insert(this.clone());
+
+        Ok(this)
+    }
+
+    fn node() -> ProviderNode {
+        ProviderNode::shared::<Self>(T::node(), Shared::Arc)
+    }
+}
+
+impl<T> AsyncProvider for Rc<T>
+where
+    T: AsyncProvider,
+{
+    async fn build_async(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+        if let Some(t) = ctx.get_cloned::<Self>() {
+            return Ok(t);
+        }
+
+        let t = ctx.build_async::<T>().await?;
+        let this = Rc::new(t);
+        ctx.insert(this.clone());
+
+        Ok(this)
+    }
+
+    fn node() -> ProviderNode {
+        ProviderNode::shared::<Self>(T::node(), Shared::Rc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pool;
+    struct Repo(Arc<Pool>);
+
+    impl AsyncProvider for Pool {
+        async fn build_async(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            tokio::task::yield_now().await;
+            if ctx.get::<bool>().is_some() {
+                ctx.build_async::<Repo>().await?;
+            }
+            Ok(Pool)
+        }
+    }
+
+    impl AsyncProvider for Repo {
+        async fn build_async(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            ctx.build::<()>()?;
+            Ok(Repo(ctx.build_async().await?))
+        }
+    }
+
+    #[tokio::test]
+    async fn t_build_async() {
+        let mut ctx = ProviderContext::new();
+        let repo = ctx.build_async::<Repo>().await.unwrap();
+        let pool = ctx.build_async::<Arc<Pool>>().await.unwrap();
+        assert!(Arc::ptr_eq(&repo.0, &pool));
+
+        let err = Repo::provide_async_with(|ctx| {
+            ctx.insert(true);
+        })
+        .await
+        .err()
+        .unwrap();
+        assert!(
+            format!("{err:#}").contains("Repo -> Arc<Pool> -> Pool -> Repo"),
+            "{err:#}"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::{any::TypeId, sync::Arc};
+
+use serde::de::DeserializeOwned;
+
+use super::{ProviderContext, SyncProviderContext};
+use crate::configs::{ConfigLoader, ConfigRoot, ConfigSource, ValidateConfig};
+
+impl ProviderContext {
+    /// Registers `root` as the configuration that `#[provider(config)]`
+    /// fields are resolved from.
+    pub fn insert_config<C: ConfigRoot>(&mut self, root: C) {
+        self.insert_config_source(Arc::new(root) as Arc<dyn ConfigRoot>);
+    }
+
+    /// Registers a root configuration that may change between builds, such
+    /// as a [`ReloadableConfig`](crate::configs::ReloadableConfig).
+    pub fn insert_config_source<S: ConfigSource>(&mut self, source: S) {
+        self.insert::<Arc<dyn ConfigSource>>(Arc::new(source));
+    }
+
+    pub fn with_config<C: ConfigRoot>(mut self, root: C) -> Self {
+        self.insert_config(root);
+        self
+    }
+
+    /// Loads `C` with `loader` and registers it as the root configuration.
+    pub fn load_config<C>(&mut self, loader: &ConfigLoader) -> anyhow::Result<()>
+    where
+        C: ConfigRoot + DeserializeOwned,
+    {
+        self.insert_config(loader.load::<C>()?);
+        Ok(())
+    }
+
+    /// Like [`ProviderContext::load_config`], but refuses a config that
+    /// fails validation.
+    pub fn load_validated_config<C>(&mut self, loader: &ConfigLoader) -> anyhow::Result<()>
+    where
+        C: ConfigRoot + DeserializeOwned + ValidateConfig,
+    {
+        self.insert_config(loader.load_validated::<C>()?);
+        Ok(())
+    }
+
+    /// Returns the root configuration registered in this context, its
+    /// parents or the [`SyncProviderContext`] it was scoped from.
+    pub fn config_root(&self) -> Option<Arc<dyn ConfigRoot>> {
+        self.get_cloned::<Arc<dyn ConfigSource>>()
+            .map(|source| source.current_root())
+    }
+
+    /// Clones the section of type `T` out of the root configuration.
+    pub fn config<T: Clone + 'static>(&self) -> Option<T> {
+        let root = self.config_root()?;
+        root.get_section(TypeId::of::<T>())?
+            .downcast_ref::<T>()
+            .cloned()
+    }
+
+    /// Returns the section of type `Arc<T>`, or wraps a clone of the section
+    /// of type `T`.
+    pub fn config_arc<T: Clone + 'static>(&self) -> Option<Arc<T>> {
+        self.config::<Arc<T>>()
+            .or_else(|| self.config::<T>().map(Arc::new))
+    }
+}
+
+impl SyncProviderContext {
+    /// See [`ProviderContext::insert_config`].
+    pub fn insert_config<C: ConfigRoot>(&self, root: C) {
+        self.insert_config_source(Arc::new(root) as Arc<dyn ConfigRoot>);
+    }
+
+    /// See [`ProviderContext::insert_config_source`].
+    pub fn insert_config_source<S: ConfigSource>(&self, source: S) {
+        self.insert::<Arc<dyn ConfigSource>>(Arc::new(source));
+    }
+
+    pub fn with_config<C: ConfigRoot>(self, root: C) -> Self {
+        self.insert_config(root);
+        self
+    }
+
+    /// See [`ProviderContext::load_config`].
+    pub fn load_config<C>(&self, loader: &ConfigLoader) -> anyhow::Result<()>
+    where
+        C: ConfigRoot + DeserializeOwned,
+    {
+        self.insert_config(loader.load::<C>()?);
+        Ok(())
+    }
+
+    /// See [`ProviderContext::load_validated_config`].
+    pub fn load_validated_config<C>(&self, loader: &ConfigLoader) -> anyhow::Result<()>
+    where
+        C: ConfigRoot + DeserializeOwned + ValidateConfig,
+    {
+        self.insert_config(loader.load_validated::<C>()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+
+    use super::*;
+    use crate::configs::GetConfig;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct DbConfig {
+        url: String,
+    }
+
+    struct AppConfig {
+        db: DbConfig,
+    }
+
+    impl GetConfig<DbConfig> for AppConfig {
+        fn get_config(&self) -> &DbConfig {
+            &self.db
+        }
+    }
+
+    impl ConfigRoot for AppConfig {
+        fn get_section(&self, type_id: TypeId) -> Option<&dyn Any> {
+            if type_id == TypeId::of::<DbConfig>() {
+                return Some(<Self as GetConfig<DbConfig>>::get_config(self));
+            }
+            None
+        }
+    }
+
+    #[test]
+    fn t_config_sections() {
+        let app = Arc::new(SyncProviderContext::new().with_config(AppConfig {
+            db: DbConfig {
+                url: "pg://".into(),
+            },
+        }));
+        let ctx = app.scope();
+
+        assert_eq!(ctx.config::<DbConfig>().unwrap().url, "pg://");
+        assert_eq!(ctx.config_arc::<DbConfig>().unwrap().url, "pg://");
+        assert_eq!(ctx.config::<u32>(), None);
+    }
+}
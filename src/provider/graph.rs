@@ -0,0 +1,274 @@
+use std::{any::TypeId, collections::HashMap, fmt::Write};
+
+use serde::Serialize;
+
+use super::{AsyncProvider, Provider, short_type_name};
+
+/// Static description of how a provider builds itself, emitted by
+/// `#[derive(Provider)]` and `#[derive(AsyncProvider)]`.
+#[derive(Debug, Clone)]
+pub struct ProviderNode {
+    pub type_id: TypeId,
+    pub type_name: &'static str,
+    pub shared: Option<Shared>,
+    pub dependencies: Vec<Dependency>,
+}
+
+impl ProviderNode {
+    pub fn new<T: 'static>(dependencies: Vec<Dependency>) -> Self {
+        ProviderNode {
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            shared: None,
+            dependencies,
+        }
+    }
+
+    pub fn leaf<T: 'static>() -> Self {
+        Self::new::<T>(vec![])
+    }
+
+    /// Describes `Wrapper` as a shared handle around `inner`.
+    pub fn shared<Wrapper: 'static>(inner: ProviderNode, shared: Shared) -> Self {
+        ProviderNode {
+            type_id: TypeId::of::<Wrapper>(),
+            type_name: std::any::type_name::<Wrapper>(),
+            shared: Some(shared),
+            dependencies: inner.dependencies,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub field: &'static str,
+    pub type_name: &'static str,
+    pub kind: DependencyKind,
+    /// Describes the dependency itself. `None` for kinds that are not built by
+    /// a provider.
+    pub node: Option<fn() -> ProviderNode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DependencyKind {
+    Provider,
+    Async,
+    /// A `Box`, `Arc` or `Rc` trait object resolved through
+    /// [`ProviderContext::bind`](super::ProviderContext::bind).
+    Bound,
+    Instance,
+    /// Cloned from an instance registered with
+    /// [`ProviderContext::insert_named`](super::ProviderContext::insert_named)
+    /// or [`ProviderContext::insert_tagged`](super::ProviderContext::insert_tagged).
+    Named,
+    /// A section of the root configuration registered with
+    /// [`ProviderContext::insert_config`](super::ProviderContext::insert_config).
+    Config,
+    Default,
+    With,
+}
+
+/// Shared singletons cached in the `ProviderContext`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Shared {
+    Arc,
+    Rc,
+}
+
+/// The dependency graph reachable from a root provider.
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyGraph {
+    pub root: usize,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    pub id: usize,
+    pub type_name: &'static str,
+    pub shared: Option<Shared>,
+    /// `false` for leaves that are taken from the context or constructed
+    /// in place rather than built by a provider.
+    pub provided: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+    pub field: &'static str,
+    pub kind: DependencyKind,
+}
+
+impl DependencyGraph {
+    pub fn of<T: Provider>() -> Self {
+        Self::from_node(T::node())
+    }
+
+    pub fn of_async<T: AsyncProvider>() -> Self {
+        Self::from_node(T::node())
+    }
+
+    pub fn from_node(root: ProviderNode) -> Self {
+        let mut walker = Walker::default();
+        let root = walker.visit(root);
+        DependencyGraph {
+            root,
+            nodes: walker.nodes,
+            edges: walker.edges,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("DependencyGraph is always serializable")
+    }
+
+    /// Renders the graph in Graphviz DOT format. Shared singletons are drawn
+    /// with a double border and context leaves with a dashed one.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph providers {\n    node [shape=box];\n");
+        for node in &self.nodes {
+            let label = short_type_name(node.type_name);
+            let style = match (node.shared, node.provided) {
+                (Some(_), _) => ", peripheries=2",
+                (None, false) => ", style=dashed",
+                (None, true) => "",
+            };
+            writeln!(out, "    n{} [label={label:?}{style}];", node.id).unwrap();
+        }
+        for edge in &self.edges {
+            let label = match edge.kind {
+                DependencyKind::Provider => edge.field.to_string(),
+                kind => format!("{} ({kind:?})", edge.field),
+            };
+            writeln!(out, "    n{} -> n{} [label={label:?}];", edge.from, edge.to).unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[derive(Default)]
+struct Walker {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+    provided: HashMap<TypeId, usize>,
+    leaves: HashMap<&'static str, usize>,
+}
+
+impl Walker {
+    fn visit(&mut self, node: ProviderNode) -> usize {
+        if let Some(id) = self.provided.get(&node.type_id) {
+            return *id;
+        }
+
+        let id = self.push(node.type_name, node.shared, true);
+        self.provided.insert(node.type_id, id);
+
+        for dep in node.dependencies {
+            let to = match dep.node {
+                Some(node) => self.visit(node()),
+                None => self.leaf(dep.type_name),
+            };
+            self.edges.push(GraphEdge {
+                from: id,
+                to,
+                field: dep.field,
+                kind: dep.kind,
+            });
+        }
+
+        id
+    }
+
+    fn leaf(&mut self, type_name: &'static str) -> usize {
+        if let Some(id) = self.leaves.get(type_name) {
+            return *id;
+        }
+        let id = self.push(type_name, None, false);
+        self.leaves.insert(type_name, id);
+        id
+    }
+
+    fn push(&mut self, type_name: &'static str, shared: Option<Shared>, provided: bool) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(GraphNode {
+            id,
+            type_name,
+            shared,
+            provided,
+        });
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::provider::ProviderContext;
+
+    struct Service;
+    struct Repo;
+
+    impl Provider for Service {
+        fn build(_ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            Ok(Service)
+        }
+
+        fn node() -> ProviderNode {
+            ProviderNode::new::<Self>(vec![
+                Dependency {
+                    field: "repo",
+                    type_name: std::any::type_name::<Arc<Repo>>(),
+                    kind: DependencyKind::Provider,
+                    node: Some(<Arc<Repo> as Provider>::node),
+                },
+                Dependency {
+                    field: "name",
+                    type_name: std::any::type_name::<String>(),
+                    kind: DependencyKind::Instance,
+                    node: None,
+                },
+            ])
+        }
+    }
+
+    impl Provider for Repo {
+        fn build(_ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            Ok(Repo)
+        }
+
+        fn node() -> ProviderNode {
+            ProviderNode::new::<Self>(vec![Dependency {
+                field: "service",
+                type_name: std::any::type_name::<Service>(),
+                kind: DependencyKind::Provider,
+                node: Some(<Service as Provider>::node),
+            }])
+        }
+    }
+
+    #[test]
+    fn t_graph_export() {
+        let graph = DependencyGraph::of::<Service>();
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.nodes[1].shared, Some(Shared::Arc));
+        assert_eq!((graph.edges[0].from, graph.edges[0].to), (1, 0));
+
+        let json = graph.to_json();
+        assert_eq!(json["nodes"][2]["provided"], false);
+
+        let dot = graph.to_dot();
+        assert!(
+            dot.contains(r#"n1 [label="Arc<Repo>", peripheries=2];"#),
+            "{dot}"
+        );
+        assert!(
+            dot.contains(r#"n0 -> n2 [label="name (Instance)"];"#),
+            "{dot}"
+        );
+    }
+}
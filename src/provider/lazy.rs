@@ -0,0 +1,223 @@
+use std::{
+    cell::{OnceCell, RefCell},
+    fmt,
+};
+
+use super::{
+    Provider, ProviderContext,
+    graph::{Dependency, DependencyKind, ProviderNode},
+};
+
+/// A `T` that is built on first access instead of together with its owner.
+///
+/// The build runs in a fork of the context the `Lazy` was provided from, see
+/// [`fork_shared`](ProviderContext::fork_shared). It reuses the instances and
+/// singletons that context held at that point and has not taken since, as
+/// well as its parents, overrides and bindings. Instances it registers for
+/// disposal are disposed by the `shutdown` of that context.
+pub struct Lazy<T> {
+    value: OnceCell<T>,
+    ctx: RefCell<Option<ProviderContext>>,
+}
+
+impl<T: Provider> Lazy<T> {
+    /// Returns the value, building it on first call. A failed build is
+    /// retried on the next call.
+    pub fn get(&self) -> anyhow::Result<&T> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+
+        let ctx = self
+            .ctx
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("Lazy::get: recursive initialization"))?;
+        let res = ctx.fork().build::<T>();
+        match res {
+            Ok(value) => Ok(self.value.get_or_init(|| value)),
+            Err(err) => {
+                *self.ctx.borrow_mut() = Some(ctx);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn is_built(&self) -> bool {
+        self.value.get().is_some()
+    }
+}
+
+impl<T: Provider> Provider for Lazy<T> {
+    fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+        Ok(Lazy {
+            value: OnceCell::new(),
+            ctx: RefCell::new(Some(ctx.fork_shared())),
+        })
+    }
+
+    fn node() -> ProviderNode {
+        ProviderNode::new::<Self>(vec![inner_dependency::<T>()])
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Lazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Lazy").field(&self.value.get()).finish()
+    }
+}
+
+/// Builds a fresh `T` on every call, e.g. one per spawned task.
+///
+/// Like [`Lazy`], each build runs in a fork of the context the `Factory` was
+/// provided from and sees the instances it held.
+pub struct Factory<T> {
+    ctx: ProviderContext,
+    _marker: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: Provider> Factory<T> {
+    pub fn create(&self) -> anyhow::Result<T> {
+        self.ctx.fork().build()
+    }
+}
+
+impl<T: Provider> Provider for Factory<T> {
+    fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+        Ok(Factory {
+            ctx: ctx.fork_shared(),
+            _marker: std::marker::PhantomData,
+        })
+    }
+
+    fn node() -> ProviderNode {
+        ProviderNode::new::<Self>(vec![inner_dependency::<T>()])
+    }
+}
+
+fn inner_dependency<T: Provider>() -> Dependency {
+    Dependency {
+        field: "",
+        type_name: std::any::type_name::<T>(),
+        kind: DependencyKind::Provider,
+        node: Some(T::node),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    thread_local! {
+        static BUILT: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Conn(Rc<Pool>);
+    struct Pool;
+
+    impl Provider for Conn {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            BUILT.set(BUILT.get() + 1);
+            Ok(Conn(ctx.build()?))
+        }
+    }
+
+    impl Provider for Pool {
+        fn build(_ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            Ok(Pool)
+        }
+    }
+
+    #[test]
+    fn t_lazy_and_factory() {
+        let mut app = ProviderContext::new();
+        let pool = app.build::<Rc<Pool>>().unwrap();
+        let mut ctx = ProviderContext::child(Rc::new(app));
+
+        let lazy = ctx.build::<Lazy<Conn>>().unwrap();
+        assert_eq!(BUILT.get(), 0);
+        assert!(Rc::ptr_eq(&lazy.get().unwrap().0, &pool));
+        lazy.get().unwrap();
+        assert!(lazy.is_built());
+        assert_eq!(BUILT.get(), 1);
+
+        let factory = ctx.build::<Factory<Conn>>().unwrap();
+        let a = factory.create().unwrap();
+        let b = factory.create().unwrap();
+        assert_eq!(BUILT.get(), 3);
+        assert!(Rc::ptr_eq(&a.0, &b.0));
+    }
+
+    struct Handler {
+        pool: Rc<Pool>,
+        lazy: Lazy<Conn>,
+        factory: Factory<Conn>,
+    }
+
+    impl Provider for Handler {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            Ok(Handler {
+                pool: ctx.build()?,
+                lazy: ctx.build()?,
+                factory: ctx.build()?,
+            })
+        }
+    }
+
+    #[test]
+    fn t_lazy_shares_sibling_singleton() {
+        let mut ctx = ProviderContext::new().with_instance(7u8);
+        let handler = ctx.build::<Handler>().unwrap();
+        let conn = handler.lazy.get().unwrap();
+        assert!(Rc::ptr_eq(&conn.0, &handler.pool));
+        assert!(Rc::ptr_eq(
+            &handler.factory.create().unwrap().0,
+            &handler.pool
+        ));
+
+        assert_eq!(ctx.build::<Lazy<Level>>().unwrap().get().unwrap().0, 7);
+    }
+
+    struct Report {
+        lazy: Lazy<Level>,
+        level: u8,
+    }
+
+    impl Provider for Report {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            let lazy = ctx.build()?;
+            let level = ctx
+                .remove::<u8>()
+                .ok_or_else(|| anyhow::anyhow!("Instance not found"))?;
+            Ok(Report { lazy, level })
+        }
+    }
+
+    #[test]
+    fn t_instance_after_lazy() {
+        let report = Report::provide_with(|ctx| {
+            ctx.insert(7u8);
+        })
+        .unwrap();
+        assert_eq!(report.level, 7);
+        assert!(report.lazy.get().is_err());
+
+        let mut ctx = ProviderContext::new().with_instance(7u8);
+        let lazy = ctx.build::<Lazy<Level>>().unwrap();
+        drop(ctx);
+        assert_eq!(lazy.get().unwrap().0, 7);
+    }
+
+    struct Level(u8);
+
+    impl Provider for Level {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            let level = ctx.get::<u8>().copied();
+            Ok(Level(
+                level.ok_or_else(|| anyhow::anyhow!("level not found"))?,
+            ))
+        }
+    }
+}
@@ -0,0 +1,418 @@
+use std::{any::TypeId, fmt, pin::Pin, rc::Rc, sync::Arc, time::Duration};
+
+use super::{Key, ProviderContext, SyncProviderContext};
+
+/// Cleanup hook for provided resources, such as flushing buffers, closing
+/// pools or stopping background tasks.
+///
+/// Instances are disposed by [`ProviderContext::shutdown`] in reverse build
+/// order. Derived providers register themselves with `#[provider(dispose)]`
+/// on the struct.
+pub trait Dispose: 'static {
+    async fn dispose(&self) -> anyhow::Result<()>;
+
+    /// Overrides the default timeout passed to [`ProviderContext::shutdown`].
+    fn dispose_timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl<T> Dispose for Arc<T>
+where
+    T: Dispose,
+{
+    async fn dispose(&self) -> anyhow::Result<()> {
+        T::dispose(self).await
+    }
+
+    fn dispose_timeout(&self) -> Option<Duration> {
+        T::dispose_timeout(self)
+    }
+}
+
+impl<T> Dispose for Rc<T>
+where
+    T: Dispose,
+{
+    async fn dispose(&self) -> anyhow::Result<()> {
+        T::dispose(self).await
+    }
+
+    fn dispose_timeout(&self) -> Option<Duration> {
+        T::dispose_timeout(self)
+    }
+}
+
+type DisposeFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>>>>;
+
+type Disposal = (Option<Duration>, DisposeFuture);
+
+type DisposeFn = Box<dyn FnOnce(&ProviderContext) -> Option<Disposal>>;
+
+/// Disposes the `Arc<T>` stored in a container. A plain `fn` so that it can
+/// be kept by the `Send + Sync` container.
+type SharedDisposeFn = fn(&SyncProviderContext) -> Option<Disposal>;
+
+#[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+pub(super) struct Disposer {
+    /// Set for registrations made by type, which are deduplicated.
+    type_id: Option<TypeId>,
+    type_name: &'static str,
+    dispose: DisposeFn,
+    /// For registrations made by type, the key of the `Arc<T>` and how to
+    /// dispose it once it is stored in the container.
+    shared: Option<(Key, SharedDisposeFn)>,
+}
+
+/// A registration moved into a [`SyncProviderContext`], see
+/// [`ProviderContext::register_dispose_of`].
+#[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+pub(super) struct SharedDisposer {
+    type_id: TypeId,
+    type_name: &'static str,
+    dispose: SharedDisposeFn,
+}
+
+fn dispose_future<D: Dispose>(instance: D) -> Disposal {
+    let timeout = instance.dispose_timeout();
+    let fut = Box::pin(async move { instance.dispose().await });
+    (timeout, fut)
+}
+
+fn dispose_shared<T: Dispose>(container: &SyncProviderContext) -> Option<Disposal> {
+    container
+        .get_by_key::<Arc<T>>(Key::of::<Arc<T>>())
+        .map(dispose_future)
+}
+
+impl Disposer {
+    /// Whether the instance is stored in the container of `ctx`, which then
+    /// disposes it instead of `ctx`.
+    fn in_container(&self, ctx: &ProviderContext) -> bool {
+        match (&self.shared, ctx.shared()) {
+            (Some((key, _)), Some(container)) => container.contains_key(*key),
+            _ => false,
+        }
+    }
+}
+
+impl ProviderContext {
+    /// Registers `instance` to be disposed on [`ProviderContext::shutdown`].
+    pub fn register_dispose<D: Dispose>(&mut self, instance: D) {
+        self.disposers.borrow_mut().push(Disposer {
+            type_id: None,
+            type_name: std::any::type_name::<D>(),
+            dispose: Box::new(move |_| Some(dispose_future(instance))),
+            shared: None,
+        });
+    }
+
+    /// Registers the `Arc<T>` or `Rc<T>` singleton cached in this context or
+    /// its parents to be disposed on [`ProviderContext::shutdown`]. Instances
+    /// of `T` that are not cached as a singleton are owned by their users and
+    /// are skipped.
+    ///
+    /// An `Arc<T>` stored in the [`SyncProviderContext`] of a scope is
+    /// disposed by [`SyncProviderContext::shutdown`] instead: the
+    /// registration moves to the container when the scope is dropped.
+    ///
+    /// Called from derived providers marked with `#[provider(dispose)]`.
+    pub fn register_dispose_of<T: Dispose>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        let mut disposers = self.disposers.borrow_mut();
+        if disposers.iter().any(|d| d.type_id == Some(type_id)) {
+            return;
+        }
+
+        disposers.push(Disposer {
+            type_id: Some(type_id),
+            type_name: std::any::type_name::<T>(),
+            dispose: Box::new(|ctx| {
+                if let Some(arc) = ctx.get_by_key::<Arc<T>>(Key::of::<Arc<T>>()) {
+                    return Some(dispose_future(arc.clone()));
+                }
+                let rc = ctx.get_by_key::<Rc<T>>(Key::of::<Rc<T>>())?;
+                Some(dispose_future(rc.clone()))
+            }),
+            shared: Some((Key::of::<Arc<T>>(), dispose_shared::<T>)),
+        });
+    }
+
+    /// Disposes registered instances in reverse build order, so that every
+    /// instance is disposed before the dependencies it was built from.
+    ///
+    /// Each instance gets `default_timeout` unless it sets its own. All
+    /// instances are disposed even if some fail; failures are collected into
+    /// a [`ShutdownError`]. Singletons stored in the container of a scope are
+    /// left to [`SyncProviderContext::shutdown`].
+    ///
+    /// Includes the instances handed back by dropped forks, such as those
+    /// built by a [`Lazy`](super::Lazy) or a [`Factory`](super::Factory).
+    #[cfg(feature = "tokio")]
+    pub async fn shutdown(&mut self, default_timeout: Duration) -> anyhow::Result<()> {
+        let disposers = std::mem::take(&mut *self.disposers.borrow_mut());
+        let mut kept = vec![];
+        let mut disposals = vec![];
+        for disposer in disposers.into_iter().rev() {
+            if disposer.in_container(self) {
+                kept.insert(0, disposer);
+                continue;
+            }
+            disposals.push((disposer.type_name, (disposer.dispose)(self)));
+        }
+        self.disposers.borrow_mut().splice(0..0, kept);
+        run_disposals(disposals, default_timeout).await
+    }
+
+    /// Hands the registrations of singletons stored in the container over to
+    /// it, skipping types it already disposes. A fork hands the rest to the
+    /// context it was forked from, bound to the instances it holds.
+    fn hand_over_disposers(&mut self) {
+        let disposers = std::mem::take(&mut *self.disposers.borrow_mut());
+        let mut forwarded = vec![];
+        for disposer in disposers {
+            if let Some(container) = self.shared.clone()
+                && disposer.in_container(self)
+            {
+                let (Some(type_id), Some((_, dispose))) = (disposer.type_id, disposer.shared)
+                else {
+                    continue;
+                };
+                let mut shared = container.disposers.lock();
+                if shared.iter().all(|d| d.type_id != type_id) {
+                    shared.push(SharedDisposer {
+                        type_id,
+                        type_name: disposer.type_name,
+                        dispose,
+                    });
+                }
+            } else if self.dispose_to.is_some()
+                && let Some(disposal) = (disposer.dispose)(self)
+            {
+                forwarded.push(Disposer {
+                    type_id: None,
+                    type_name: disposer.type_name,
+                    dispose: Box::new(move |_| Some(disposal)),
+                    shared: None,
+                });
+            }
+        }
+        if let Some(owner) = &self.dispose_to {
+            owner.borrow_mut().extend(forwarded);
+        }
+    }
+}
+
+impl Drop for ProviderContext {
+    fn drop(&mut self) {
+        self.keep_forked_instances();
+        self.hand_over_disposers();
+    }
+}
+
+impl SyncProviderContext {
+    /// Disposes the singletons registered with
+    /// [`ProviderContext::register_dispose_of`] in scopes of this container,
+    /// in reverse build order. See [`ProviderContext::shutdown`].
+    #[cfg(feature = "tokio")]
+    pub async fn shutdown(&self, default_timeout: Duration) -> anyhow::Result<()> {
+        let disposers = std::mem::take(&mut *self.disposers.lock());
+        let disposals = disposers
+            .into_iter()
+            .rev()
+            .map(|disposer| (disposer.type_name, (disposer.dispose)(self)))
+            .collect();
+        run_disposals(disposals, default_timeout).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+async fn run_disposals(
+    disposals: Vec<(&'static str, Option<Disposal>)>,
+    default_timeout: Duration,
+) -> anyhow::Result<()> {
+    let mut errors = vec![];
+    for (type_name, disposal) in disposals {
+        let Some((timeout, fut)) = disposal else {
+            continue;
+        };
+        let timeout = timeout.unwrap_or(default_timeout);
+        let err = match tokio::time::timeout(timeout, fut).await {
+            Ok(Ok(())) => continue,
+            Ok(Err(err)) => err,
+            Err(_) => anyhow::anyhow!("dispose timed out after {timeout:?}"),
+        };
+        tracing::warn!(type_name, "dispose failed: {err:#}");
+        errors.push((type_name, err));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ShutdownError { errors }.into())
+    }
+}
+
+/// All failures from one [`ProviderContext::shutdown`].
+#[derive(Debug)]
+pub struct ShutdownError {
+    pub errors: Vec<(&'static str, anyhow::Error)>,
+}
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance(s) failed to dispose", self.errors.len())?;
+        for (type_name, err) in &self.errors {
+            write!(f, "; {}: {err:#}", super::short_type_name(type_name))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShutdownError {}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::provider::Provider;
+
+    thread_local! {
+        static DISPOSED: RefCell<Vec<&'static str>> = const { RefCell::new(vec![]) };
+    }
+
+    struct Pool;
+    struct Repo(#[allow(dead_code)] Rc<Pool>);
+    struct Stuck;
+
+    impl Provider for Pool {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            ctx.register_dispose_of::<Self>();
+            Ok(Pool)
+        }
+    }
+
+    impl Provider for Repo {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            let this = Repo(ctx.build()?);
+            ctx.register_dispose_of::<Self>();
+            Ok(this)
+        }
+    }
+
+    impl Dispose for Pool {
+        async fn dispose(&self) -> anyhow::Result<()> {
+            DISPOSED.with_borrow_mut(|d| d.push("pool"));
+            Ok(())
+        }
+    }
+
+    impl Dispose for Repo {
+        async fn dispose(&self) -> anyhow::Result<()> {
+            DISPOSED.with_borrow_mut(|d| d.push("repo"));
+            anyhow::bail!("flush failed")
+        }
+    }
+
+    impl Dispose for Stuck {
+        async fn dispose(&self) -> anyhow::Result<()> {
+            std::future::pending().await
+        }
+
+        fn dispose_timeout(&self) -> Option<Duration> {
+            Some(Duration::from_millis(1))
+        }
+    }
+
+    struct Conn;
+    struct Cache(#[allow(dead_code)] Arc<Conn>);
+
+    impl Provider for Conn {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            ctx.register_dispose_of::<Self>();
+            Ok(Conn)
+        }
+
+        fn share_arc(
+            container: &SyncProviderContext,
+            build: &mut dyn FnMut() -> anyhow::Result<Arc<Self>>,
+        ) -> anyhow::Result<Arc<Self>> {
+            container.get_or_build(build)
+        }
+    }
+
+    impl Provider for Cache {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            let this = Cache(ctx.build()?);
+            ctx.register_dispose_of::<Self>();
+            Ok(this)
+        }
+
+        fn share_arc(
+            container: &SyncProviderContext,
+            build: &mut dyn FnMut() -> anyhow::Result<Arc<Self>>,
+        ) -> anyhow::Result<Arc<Self>> {
+            container.get_or_build(build)
+        }
+    }
+
+    impl Dispose for Conn {
+        async fn dispose(&self) -> anyhow::Result<()> {
+            DISPOSED.with_borrow_mut(|d| d.push("conn"));
+            Ok(())
+        }
+    }
+
+    impl Dispose for Cache {
+        async fn dispose(&self) -> anyhow::Result<()> {
+            DISPOSED.with_borrow_mut(|d| d.push("cache"));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn t_shutdown_container() {
+        let app = Arc::new(SyncProviderContext::new());
+        app.build::<Arc<Cache>>().unwrap();
+        let mut scope = app.scope();
+        scope.build::<Arc<Conn>>().unwrap();
+        scope.shutdown(Duration::from_secs(1)).await.unwrap();
+        drop(scope);
+        assert!(DISPOSED.take().is_empty());
+
+        app.shutdown(Duration::from_secs(1)).await.unwrap();
+        assert_eq!(DISPOSED.take(), ["cache", "conn"]);
+    }
+
+    #[tokio::test]
+    async fn t_shutdown_disposes_lazy_builds() {
+        let mut ctx = ProviderContext::new();
+        let lazy = ctx.build::<crate::provider::Lazy<Rc<Pool>>>().unwrap();
+        let factory = ctx.build::<crate::provider::Factory<Rc<Repo>>>().unwrap();
+        lazy.get().unwrap();
+        factory.create().unwrap();
+
+        ctx.shutdown(Duration::from_secs(1)).await.unwrap_err();
+        assert_eq!(DISPOSED.take(), ["repo", "pool", "pool"]);
+    }
+
+    #[tokio::test]
+    async fn t_shutdown_in_reverse_order() {
+        let mut ctx = ProviderContext::new();
+        ctx.register_dispose(Stuck);
+        ctx.build::<Arc<Repo>>().unwrap();
+        ctx.build::<Repo>().unwrap();
+
+        let err = ctx.shutdown(Duration::from_secs(1)).await.err().unwrap();
+        let err = err.downcast::<ShutdownError>().unwrap();
+        assert_eq!(DISPOSED.take(), ["repo", "pool"]);
+        assert_eq!(
+            err.to_string(),
+            "2 instance(s) failed to dispose; Repo: flush failed; \
+             Stuck: dispose timed out after 1ms"
+        );
+
+        assert!(ctx.shutdown(Duration::from_secs(1)).await.is_ok());
+    }
+}
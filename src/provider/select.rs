@@ -0,0 +1,44 @@
+//! Support for `#[derive(Provider)]` on enums.
+//!
+//! The variant is chosen when the enum is built, either by a `select`
+//! expression on the enum that returns the variant key, or by `when`
+//! predicates on the variants, tried in order:
+//!
+//! ```ignore
+//! #[derive(Provider)]
+//! #[provider(select = |ctx| Ok(ctx.get::<RepoConfig>().context("no RepoConfig")?.backend.clone()))]
+//! enum UserRepo {
+//!     InMemory(InMemoryRepo),
+//!     #[provider(rename = "postgres")]
+//!     Sql { pool: Arc<PgPool> },
+//! }
+//!
+//! #[derive(Provider)]
+//! enum Mailer {
+//!     #[provider(when = |ctx| ctx.get::<MailConfig>().is_some_and(|c| c.stub))]
+//!     Stub(StubMailer),
+//!     Smtp(SmtpMailer),
+//! }
+//! ```
+//!
+//! Variant keys default to the snake_case variant name. A variant without
+//! `when` is the fallback. Only the fields of the chosen variant are built.
+
+use super::ProviderContext;
+
+#[doc(hidden)]
+pub fn select_variant<F, S>(ctx: &ProviderContext, select: F) -> anyhow::Result<String>
+where
+    F: FnOnce(&ProviderContext) -> anyhow::Result<S>,
+    S: AsRef<str>,
+{
+    Ok(select(ctx)?.as_ref().to_owned())
+}
+
+#[doc(hidden)]
+pub fn variant_when<F>(ctx: &ProviderContext, when: F) -> bool
+where
+    F: FnOnce(&ProviderContext) -> bool,
+{
+    when(ctx)
+}
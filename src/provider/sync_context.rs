@@ -0,0 +1,350 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
+
+use parking_lot::{Mutex, ReentrantMutex, RwLock};
+
+use super::{Key, Provider, ProviderContext, lifecycle::SharedDisposer};
+
+/// A `Send + Sync` instance store that can be shared across worker threads.
+///
+/// Only `Send + Sync` instances are accepted. [`ProviderContext`]s created by
+/// [`SyncProviderContext::scope`] fall back to it when looking up cached
+/// `Arc<T>` and [`SingletonProvider`](super::SingletonProvider) instances,
+/// and store the ones they build in it, see
+/// [`Provider::share_arc`](super::Provider::share_arc).
+#[derive(Default)]
+pub struct SyncProviderContext {
+    map: RwLock<HashMap<Key, Box<dyn Any + Send + Sync>>>,
+    /// Serializes construction per type so concurrent builds run only once.
+    /// Reentrant, since building `T` goes through its own `share_arc`.
+    building: Mutex<HashMap<TypeId, Arc<ReentrantMutex<()>>>>,
+    /// Cleanup hooks of stored singletons in build order.
+    pub(super) disposers: Mutex<Vec<SharedDisposer>>,
+}
+
+static GLOBAL: OnceLock<Arc<SyncProviderContext>> = OnceLock::new();
+
+impl SyncProviderContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get<T>(&self) -> Option<T>
+    where
+        T: Clone + 'static,
+    {
+        self.get_by_key(Key::of::<T>())
+    }
+
+    pub fn get_named<T>(&self, name: &'static str) -> Option<T>
+    where
+        T: Clone + 'static,
+    {
+        self.get_by_key(Key::named::<T>(name))
+    }
+
+    pub fn get_tagged<Tag, T>(&self) -> Option<T>
+    where
+        Tag: 'static,
+        T: Clone + 'static,
+    {
+        self.get_by_key(Key::tagged::<Tag, T>())
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.contains_key(Key::of::<T>())
+    }
+
+    pub fn insert<T>(&self, val: T) -> Option<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.insert_by_key(Key::of::<T>(), val)
+    }
+
+    pub fn insert_named<T>(&self, name: &'static str, val: T) -> Option<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.insert_by_key(Key::named::<T>(name), val)
+    }
+
+    pub fn insert_tagged<Tag, T>(&self, val: T) -> Option<T>
+    where
+        Tag: 'static,
+        T: Send + Sync + 'static,
+    {
+        self.insert_by_key(Key::tagged::<Tag, T>(), val)
+    }
+
+    pub(super) fn contains_key(&self, key: Key) -> bool {
+        self.map.read().contains_key(&key)
+    }
+
+    pub(super) fn get_by_key<T: Clone + 'static>(&self, key: Key) -> Option<T> {
+        self.map
+            .read()
+            .get(&key)
+            .and_then(|boxed| boxed.downcast_ref::<T>())
+            .cloned()
+    }
+
+    fn insert_by_key<T>(&self, key: Key, val: T) -> Option<T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.map
+            .write()
+            .insert(key, Box::new(val))
+            .and_then(|boxed| boxed.downcast().ok().map(|boxed| *boxed))
+    }
+
+    /// Returns the stored `T`, or stores `val` and returns it.
+    pub fn get_or_insert<T>(&self, val: T) -> T
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let mut map = self.map.write();
+        let stored = map.entry(Key::of::<T>()).or_insert_with(|| Box::new(val));
+        stored
+            .downcast_ref::<T>()
+            .expect("instances are stored under their own type")
+            .clone()
+    }
+
+    pub fn with_instance<I>(self, instance: I) -> Self
+    where
+        I: Send + Sync + 'static,
+    {
+        self.insert(instance);
+        self
+    }
+
+    /// Installs `this` as the application-wide container, returned by
+    /// [`SyncProviderContext::global`]. Fails if one is already installed.
+    pub fn set_global(this: Arc<Self>) -> Result<(), Arc<Self>> {
+        GLOBAL.set(this)
+    }
+
+    pub fn global() -> Option<&'static Arc<Self>> {
+        GLOBAL.get()
+    }
+
+    /// Creates a [`ProviderContext`] scoped from the global container, or a
+    /// standalone one if none is installed.
+    pub fn global_scope() -> ProviderContext {
+        match Self::global() {
+            Some(global) => global.scope(),
+            None => ProviderContext::new(),
+        }
+    }
+
+    /// Creates a [`ProviderContext`] that falls back to this one for lookups.
+    pub fn scope(self: &Arc<Self>) -> ProviderContext {
+        ProviderContext::with_fallbacks(None, Some(self.clone()))
+    }
+
+    /// Returns the cached `T`, or builds it in a fresh scope and caches it.
+    ///
+    /// Concurrent calls for the same type build it only once; the others wait
+    /// and receive a clone. Use this with `Arc<T>` or a
+    /// [`SingletonProvider`](super::SingletonProvider) to share one instance
+    /// between threads. Nested `Arc<U>` dependencies are cached here too when
+    /// `U` shares them, see [`Provider::share_arc`](super::Provider::share_arc).
+    pub fn build<T>(self: &Arc<Self>) -> anyhow::Result<T>
+    where
+        T: Provider + Clone + Send + Sync,
+    {
+        self.get_or_build(|| self.scope().build::<T>())
+    }
+
+    /// Returns the stored `T`, or builds it with `build` and stores it.
+    ///
+    /// Concurrent calls for the same type build it only once; the others wait
+    /// and receive a clone.
+    pub fn get_or_build<T>(&self, build: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        if let Some(this) = self.get::<T>() {
+            return Ok(this);
+        }
+
+        let lock = self
+            .building
+            .lock()
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .clone();
+        let _guard = lock.lock();
+
+        if let Some(this) = self.get::<T>() {
+            return Ok(this);
+        }
+
+        let this = build()?;
+        Ok(self.get_or_insert(this))
+    }
+}
+
+/// Picks the `share_arc` and `share_singleton` of derived providers by
+/// autoref specialization: types that are `Send + Sync` are stored in the
+/// container, others are kept in the scope. In generic impls the bounds can
+/// not be proven, so they always pick the scope.
+#[doc(hidden)]
+pub mod share {
+    use std::marker::PhantomData;
+
+    use super::SyncProviderContext;
+
+    /// For [`AsyncProvider::share_arc`](crate::provider::AsyncProvider::share_arc).
+    pub type ShareFn<T> = fn(T, &SyncProviderContext) -> T;
+
+    /// For [`Provider::share_arc`](crate::provider::Provider::share_arc).
+    pub type BuildFn<T> =
+        fn(&SyncProviderContext, &mut dyn FnMut() -> anyhow::Result<T>) -> anyhow::Result<T>;
+
+    pub struct Share<T>(PhantomData<fn() -> T>);
+
+    impl<T> Share<T> {
+        #[allow(clippy::new_without_default)]
+        pub fn new() -> Self {
+            Share(PhantomData)
+        }
+    }
+
+    pub trait ShareSync<T> {
+        fn share_fn(&self) -> ShareFn<T>;
+        fn build_fn(&self) -> BuildFn<T>;
+    }
+
+    impl<T: Clone + Send + Sync + 'static> ShareSync<T> for &Share<T> {
+        fn share_fn(&self) -> ShareFn<T> {
+            |this, container| container.get_or_insert(this)
+        }
+
+        fn build_fn(&self) -> BuildFn<T> {
+            |container, build| container.get_or_build(build)
+        }
+    }
+
+    pub trait ShareLocal<T> {
+        fn share_fn(&self) -> ShareFn<T>;
+        fn build_fn(&self) -> BuildFn<T>;
+    }
+
+    impl<T> ShareLocal<T> for Share<T> {
+        fn share_fn(&self) -> ShareFn<T> {
+            |this, _| this
+        }
+
+        fn build_fn(&self) -> BuildFn<T> {
+            |_, build| build()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    static BUILT: AtomicUsize = AtomicUsize::new(0);
+
+    struct Pool;
+
+    impl Provider for Pool {
+        fn build(_ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            BUILT.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            Ok(Pool)
+        }
+    }
+
+    struct Repo(Arc<Pool>);
+
+    impl Provider for Repo {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            Ok(Repo(ctx.build()?))
+        }
+    }
+
+    #[test]
+    fn t_build_once_across_threads() {
+        let app = Arc::new(SyncProviderContext::new().with_instance(1u8));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let app = app.clone();
+                thread::spawn(move || app.build::<Arc<Pool>>().unwrap())
+            })
+            .collect();
+        let pools: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(BUILT.load(Ordering::SeqCst), 1);
+        assert!(pools.iter().all(|p| Arc::ptr_eq(p, &pools[0])));
+
+        let handle = {
+            let app = app.clone();
+            thread::spawn(move || {
+                let mut ctx = app.scope();
+                assert_eq!(ctx.get_cloned::<u8>(), Some(1));
+                ctx.build::<Repo>().unwrap().0
+            })
+        };
+        assert!(Arc::ptr_eq(&handle.join().unwrap(), &pools[0]));
+        assert_eq!(BUILT.load(Ordering::SeqCst), 1);
+    }
+
+    thread_local! {
+        static CONNECTED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    struct Conn;
+
+    impl Provider for Conn {
+        fn build(_ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            CONNECTED.set(CONNECTED.get() + 1);
+            Ok(Conn)
+        }
+
+        fn share_arc(
+            container: &SyncProviderContext,
+            build: &mut dyn FnMut() -> anyhow::Result<Arc<Self>>,
+        ) -> anyhow::Result<Arc<Self>> {
+            container.get_or_build(build)
+        }
+    }
+
+    struct UserRepo(Arc<Conn>);
+    struct OrderRepo(Arc<Conn>);
+
+    impl Provider for UserRepo {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            Ok(UserRepo(ctx.build()?))
+        }
+    }
+
+    impl Provider for OrderRepo {
+        fn build(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+            Ok(OrderRepo(ctx.build()?))
+        }
+    }
+
+    #[test]
+    fn t_shared_dependency_built_once() {
+        let app = Arc::new(SyncProviderContext::new());
+        let users = app.build::<Arc<UserRepo>>().unwrap();
+        let orders = app.build::<Arc<OrderRepo>>().unwrap();
+        assert_eq!(CONNECTED.get(), 1);
+        assert!(Arc::ptr_eq(&users.0, &orders.0));
+        assert!(Arc::ptr_eq(&users.0, &app.get::<Arc<Conn>>().unwrap()));
+    }
+}
@@ -35,6 +35,12 @@ pub mod default_impl {
         type_name: &'static str,
     }
 
+    impl Default for HashMapTaskChanStorage {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     impl HashMapTaskChanStorage {
         pub fn new() -> Self {
             static GLOBAL_MAP: LazyLock<
@@ -58,7 +64,7 @@ pub mod default_impl {
     {
         fn insert(&self, task_id: Id, chan: Chan) -> Option<Chan> {
             let mut lock = self.map.write();
-            let task_map = lock.entry(task_id.type_id()).or_insert_with(HashMap::new);
+            let task_map = lock.entry(task_id.type_id()).or_default();
             let old = task_map.insert(
                 task_id.to_string(),
                 TaskChanWithTypeName {
@@ -0,0 +1,253 @@
+//! 128-bit [`SysId`](crate::entity::SysId)s for entities exposed publicly,
+//! which are time-ordered but, unlike `flake_id!`s, not guessable.
+//!
+//! ```ignore
+//! uuid_id!(OrderId, @serde, @diesel-pg);
+//! ulid_id!(InvoiceId, @serde, @redis);
+//! ```
+//!
+//! Both store as the postgres `uuid` type with `@diesel-pg`, and as their
+//! string form with `@redis`.
+
+/// Implemented for the inner value of `uuid_id!` and `ulid_id!` types.
+#[doc(hidden)]
+pub trait Id128: Copy + std::fmt::Display + std::str::FromStr {
+    fn generate() -> Self;
+    fn to_bytes(&self) -> [u8; 16];
+    fn from_bytes(bytes: [u8; 16]) -> Self;
+}
+
+#[cfg(feature = "uuid")]
+impl Id128 for uuid::Uuid {
+    fn generate() -> Self {
+        uuid::Uuid::now_v7()
+    }
+
+    fn to_bytes(&self) -> [u8; 16] {
+        *self.as_bytes()
+    }
+
+    fn from_bytes(bytes: [u8; 16]) -> Self {
+        uuid::Uuid::from_bytes(bytes)
+    }
+}
+
+/// Shared by all `ulid_id!` types, so that ids generated within the same
+/// millisecond still increase.
+#[cfg(feature = "ulid")]
+static ULID_GENERATOR: parking_lot::Mutex<ulid::Generator> =
+    parking_lot::Mutex::new(ulid::Generator::new());
+
+#[cfg(feature = "ulid")]
+impl Id128 for ulid::Ulid {
+    fn generate() -> Self {
+        // Within a millisecond the random part is incremented, and overflows
+        // when it started close to its maximum. Wait for the next millisecond
+        // then, holding the lock so that ids keep increasing.
+        let mut generator = ULID_GENERATOR.lock();
+        loop {
+            match generator.generate() {
+                Ok(id) => return id,
+                Err(_) => std::thread::yield_now(),
+            }
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; 16] {
+        ulid::Ulid::to_bytes(self)
+    }
+
+    fn from_bytes(bytes: [u8; 16]) -> Self {
+        ulid::Ulid::from_bytes(bytes)
+    }
+}
+
+/// A UUIDv7 id: `uuid_id!(OrderId, @serde, @diesel-pg, @redis, @graphql)`.
+#[cfg(feature = "uuid")]
+#[macro_export]
+macro_rules! uuid_id {
+    ($type_name:ident $($tt:tt)*) => {
+        $crate::id128!($type_name, $crate::uuid::Uuid $($tt)*);
+    };
+}
+
+/// A ULID: `ulid_id!(InvoiceId, @serde, @diesel-pg, @redis, @graphql)`.
+///
+/// Ids generated in the same process are monotonic, also within the same
+/// millisecond.
+#[cfg(feature = "ulid")]
+#[macro_export]
+macro_rules! ulid_id {
+    ($type_name:ident $($tt:tt)*) => {
+        $crate::id128!($type_name, $crate::ulid::Ulid $($tt)*);
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! id128 {
+    ($type_name:ident, $inner:ty $(, $($tt:tt)*)?) => {
+        impl $type_name {
+            pub fn generate() -> $type_name {
+                $type_name(<$inner as $crate::uuid_id::Id128>::generate())
+            }
+        }
+
+        impl Default for $type_name {
+            fn default() -> Self {
+                Self::generate()
+            }
+        }
+
+        impl<'a> From<&'a $type_name> for $inner {
+            fn from(id: &'a $type_name) -> Self {
+                id.0
+            }
+        }
+
+        impl<'a> From<&'a $type_name> for $type_name {
+            fn from(id: &'a $type_name) -> Self {
+                *id
+            }
+        }
+
+        impl $crate::entity::SysId for $type_name {
+            fn generate() -> Self {
+                Self::generate()
+            }
+        }
+
+        $crate::id128!(@impl $type_name, $inner, {Debug,PartialEq,PartialOrd,Eq,Ord,Hash,Clone,Copy,}, {}, $($($tt)*)? ,);
+    };
+
+    (@impl $type_name:ident, $inner:ty, {$($derives:tt)*}, {$($attrs:tt)*}, @redis, $($tail:tt)*) => {
+        impl ::redis::ToRedisArgs for $type_name {
+            fn write_redis_args<W: ?Sized>(&self, out: &mut W)
+            where
+                W: ::redis::RedisWrite,
+            {
+                out.write_arg_fmt(self.0)
+            }
+        }
+
+        impl ::redis::FromRedisValue for $type_name {
+            fn from_redis_value(v: &redis::Value) -> ::redis::RedisResult<Self> {
+                let id = String::from_redis_value(v)?;
+                let id = id.parse().map_err(|err| {
+                    ::redis::RedisError::from((
+                        ::redis::ErrorKind::TypeError,
+                        concat!("invalid ", stringify!($type_name)),
+                        format!("{err}"),
+                    ))
+                })?;
+                Ok($type_name(id))
+            }
+        }
+
+        $crate::id128!(@impl $type_name, $inner, {$($derives)*}, {$($attrs)*}, $($tail)* ,);
+    };
+
+    (@impl $type_name:ident, $inner:ty, {$($derives:tt)*}, {$($attrs:tt)*}, @serde, $($tail:tt)*) => {
+        impl ::serde::Serialize for $type_name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                serializer.collect_str(&self.0)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $type_name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                let id = String::deserialize(deserializer)?;
+                let id = id.parse().map_err(serde::de::Error::custom)?;
+                Ok(Self(id))
+            }
+        }
+
+        $crate::id128!(@impl $type_name, $inner, {$($derives)*}, {$($attrs)*}, $($tail)* ,);
+    };
+
+    (@impl $type_name:ident, $inner:ty, {$($derives:tt)*}, {$($attrs:tt)*}, @diesel-pg, $($tail:tt)*) => {
+        const _: () = {
+            use ::diesel::{
+                backend::Backend,
+                deserialize::{self, FromSql},
+                serialize::{self, IsNull, Output, ToSql},
+                sql_types::Uuid,
+            };
+            use ::std::io::Write;
+            type DbType = ::diesel::pg::Pg;
+
+            impl ToSql<Uuid, DbType> for $type_name {
+                fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DbType>) -> serialize::Result {
+                    out.write_all(&<$inner as $crate::uuid_id::Id128>::to_bytes(&self.0))?;
+                    Ok(IsNull::No)
+                }
+            }
+
+            impl FromSql<Uuid, DbType> for $type_name {
+                fn from_sql(bytes: <DbType as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+                    let bytes = <[u8; 16]>::try_from(bytes.as_bytes())?;
+                    Ok(Self(<$inner as $crate::uuid_id::Id128>::from_bytes(bytes)))
+                }
+            }
+        };
+        $crate::id128!(@impl $type_name, $inner,
+            {$($derives)* ::diesel:: AsExpression, ::diesel::FromSqlRow,},
+            {$($attrs)* #[diesel(sql_type = ::diesel::sql_types::Uuid)] },
+             $($tail)* ,
+        );
+    };
+
+    (@impl $type_name:ident, $inner:ty, {$($derives:tt)*}, {$($attrs:tt)*}, @graphql, $($tail:tt)*) => {
+        ::async_graphql::scalar!($type_name);
+        $crate::id128!(@impl $type_name, $inner, {$($derives)*}, {$($attrs)*}, $($tail)* ,);
+    };
+
+    (@impl $type_name:ident, $inner:ty, {$($derives:tt)*}, {$($attrs:tt)*}, $(,)*) => {
+        #[allow(unused_imports)]
+        use $crate::*;
+
+        #[derive(
+        $($derives)*
+        $crate::derive_more::From,
+        $crate::derive_more::Display,
+        $crate::derive_more::FromStr,
+        )]
+        $($attrs)*
+        pub struct $type_name(pub $inner);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn t_uuid_id() {
+        uuid_id!(OrderId);
+
+        let id1 = OrderId::generate();
+        let id2 = OrderId::generate();
+        assert!(id1 < id2);
+        assert_eq!(id1.0.get_version_num(), 7);
+        assert_eq!(id1.to_string().parse::<OrderId>().unwrap(), id1);
+    }
+
+    #[cfg(feature = "ulid")]
+    #[test]
+    fn t_ulid_id() {
+        ulid_id!(InvoiceId);
+
+        let id = InvoiceId::generate();
+        assert_eq!(id.to_string().len(), 26);
+        assert_eq!(id.to_string().parse::<InvoiceId>().unwrap(), id);
+        assert!("not-a-ulid".parse::<InvoiceId>().is_err());
+
+        let ids: Vec<_> = (0..1000).map(|_| InvoiceId::generate()).collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    }
+}
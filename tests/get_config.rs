@@ -0,0 +1,91 @@
+use std::{rc::Rc, sync::Arc};
+
+use lolibaso::GetConfig;
+use lolibaso::configs::GetConfig as _;
+use lolibaso::provider::SyncProviderContext;
+
+#[derive(Clone, Debug, PartialEq)]
+struct DbConfig {
+    url: String,
+}
+
+#[derive(GetConfig)]
+#[config(root)]
+struct AppConfig {
+    db: DbConfig,
+    port: u16,
+}
+
+#[test]
+fn t_derive_config_root() {
+    let app = Arc::new(SyncProviderContext::new().with_config(AppConfig {
+        db: DbConfig {
+            url: "pg://".into(),
+        },
+        port: 8080,
+    }));
+    let ctx = app.scope();
+
+    assert_eq!(ctx.config::<DbConfig>().unwrap().url, "pg://");
+    assert_eq!(ctx.config::<u16>(), Some(8080));
+    assert_eq!(ctx.config::<u32>(), None);
+}
+
+/// Not `Send`, so it can only derive `GetConfig` without `#[config(root)]`.
+#[derive(GetConfig)]
+struct LocalConfig {
+    names: Rc<Vec<String>>,
+}
+
+#[test]
+fn t_derive_without_root() {
+    let config = LocalConfig {
+        names: Rc::new(vec!["a".into()]),
+    };
+    let names: &Rc<Vec<String>> = config.get_config();
+    assert_eq!(names.len(), 1);
+}
+
+#[derive(GetConfig)]
+struct HttpConfig {
+    port: u16,
+    #[config(skip)]
+    _admin_port: u16,
+    tls: TlsConfig,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct TlsConfig {
+    cert: String,
+}
+
+#[derive(GetConfig)]
+#[config(root)]
+struct ServerConfig {
+    name: String,
+    #[config(flatten)]
+    http: HttpConfig,
+}
+
+#[test]
+fn t_flatten_and_skip() {
+    let config = ServerConfig {
+        name: "api".into(),
+        http: HttpConfig {
+            port: 8080,
+            _admin_port: 9090,
+            tls: TlsConfig {
+                cert: "cert.pem".into(),
+            },
+        },
+    };
+    let port: &u16 = config.get_config();
+    let tls: &TlsConfig = config.get_config();
+    assert_eq!((*port, tls.cert.as_str()), (8080, "cert.pem"));
+
+    let app = Arc::new(SyncProviderContext::new().with_config(config));
+    let ctx = app.scope();
+    assert_eq!(ctx.config::<String>().unwrap(), "api");
+    assert_eq!(ctx.config::<u16>(), Some(8080));
+    assert_eq!(ctx.config::<TlsConfig>().unwrap().cert, "cert.pem");
+}
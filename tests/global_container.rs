@@ -0,0 +1,19 @@
+//! Installs the process-wide container, so it runs in its own test binary.
+
+use std::sync::Arc;
+
+use lolibaso::provider::SyncProviderContext;
+
+#[test]
+fn t_global_container() {
+    assert!(SyncProviderContext::global().is_none());
+    assert!(SyncProviderContext::global_scope().shared().is_none());
+
+    let app = Arc::new(SyncProviderContext::new().with_instance(7u16));
+    SyncProviderContext::set_global(app.clone()).ok().unwrap();
+    assert!(SyncProviderContext::set_global(Arc::default()).is_err());
+
+    let ctx = SyncProviderContext::global_scope();
+    assert_eq!(ctx.get_cloned::<u16>(), Some(7));
+    assert!(Arc::ptr_eq(ctx.shared().unwrap(), &app));
+}
@@ -0,0 +1,447 @@
+use std::{
+    marker::PhantomData,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use lolibaso::provider::{
+    AsyncProvider, Lazy, Provider as _, ProviderContext, SyncProviderContext,
+    graph::{DependencyGraph, DependencyKind, Shared},
+};
+use lolibaso::{AsyncProvider, Provider};
+
+#[derive(Provider)]
+struct Clock;
+
+struct Pool {
+    url: String,
+}
+
+impl AsyncProvider for Pool {
+    async fn build_async(ctx: &mut ProviderContext) -> anyhow::Result<Self> {
+        tokio::task::yield_now().await;
+        let url = ctx.get::<String>().cloned().unwrap_or_default();
+        Ok(Pool { url })
+    }
+}
+
+#[derive(AsyncProvider)]
+struct UserRepo {
+    #[provider(async)]
+    pool: Arc<Pool>,
+    _clock: Clock,
+    #[provider(default)]
+    retries: u8,
+}
+
+#[derive(AsyncProvider)]
+struct UserService {
+    #[provider(async)]
+    repo: UserRepo,
+    #[provider(async)]
+    pool: Arc<Pool>,
+}
+
+#[tokio::test]
+async fn t_derive_async_provider() {
+    let service = UserService::provide_async_with(|ctx| {
+        ctx.insert("postgres://".to_string());
+    })
+    .await
+    .unwrap();
+    assert_eq!(service.pool.url, "postgres://");
+    assert!(Arc::ptr_eq(&service.pool, &service.repo.pool));
+    assert_eq!(service.repo.retries, 0);
+}
+
+#[derive(Provider)]
+struct Mailer {
+    _clock: Arc<Clock>,
+    #[provider(instance)]
+    _sender: String,
+    #[provider(with = 3)]
+    _retries: u8,
+}
+
+#[test]
+fn t_derived_node() {
+    let graph = DependencyGraph::of::<Mailer>();
+    let names: Vec<_> = graph.nodes.iter().map(|n| n.type_name).collect();
+    assert!(names[0].ends_with("Mailer"), "{names:?}");
+    assert_eq!(graph.nodes[1].shared, Some(Shared::Arc));
+    assert!(
+        graph.nodes[1]
+            .type_name
+            .ends_with("Arc<provider_derive::Clock>")
+    );
+
+    let edges: Vec<_> = graph
+        .edges
+        .iter()
+        .map(|e| (e.from, e.to, e.field, e.kind))
+        .collect();
+    assert_eq!(
+        edges,
+        [
+            (0, 1, "_clock", DependencyKind::Provider),
+            (0, 2, "_sender", DependencyKind::Instance),
+            (0, 3, "_retries", DependencyKind::With),
+        ]
+    );
+
+    let graph = DependencyGraph::of_async::<UserService>();
+    assert_eq!(graph.edges.last().unwrap().kind, DependencyKind::Async);
+}
+
+static POOLS: AtomicUsize = AtomicUsize::new(0);
+
+fn connect() -> usize {
+    POOLS.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Provider)]
+struct DbPool {
+    #[provider(with = connect())]
+    _id: usize,
+}
+
+#[derive(Provider)]
+struct AccountRepo {
+    pool: Arc<DbPool>,
+}
+
+#[derive(Provider)]
+struct InvoiceRepo {
+    pool: Arc<DbPool>,
+}
+
+/// Not `Send`, so its `Arc` stays in the scope that built it.
+#[derive(Provider)]
+struct LocalCache {
+    _marker: PhantomData<Rc<()>>,
+}
+
+#[test]
+fn t_container_shares_nested_singletons() {
+    let app = Arc::new(SyncProviderContext::new());
+    let accounts = app.build::<Arc<AccountRepo>>().unwrap();
+    let invoices = app.build::<Arc<InvoiceRepo>>().unwrap();
+    assert_eq!(POOLS.load(Ordering::SeqCst), 1);
+    assert!(Arc::ptr_eq(&accounts.pool, &invoices.pool));
+
+    let mut scope = app.scope();
+    assert!(Arc::ptr_eq(
+        &scope.build::<Arc<DbPool>>().unwrap(),
+        &accounts.pool
+    ));
+    scope.build::<Arc<LocalCache>>().unwrap();
+    assert!(!app.contains::<Arc<LocalCache>>());
+}
+
+static SEARCH_INDEXES: AtomicUsize = AtomicUsize::new(0);
+
+fn open_index() -> usize {
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    SEARCH_INDEXES.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Provider)]
+struct SearchIndex {
+    #[provider(with = open_index())]
+    _id: usize,
+}
+
+#[derive(Provider)]
+struct ProductSearch {
+    index: Arc<SearchIndex>,
+}
+
+#[derive(Provider)]
+struct OrderSearch {
+    index: Arc<SearchIndex>,
+}
+
+#[test]
+fn t_nested_singleton_built_once_across_threads() {
+    let app = Arc::new(SyncProviderContext::new());
+    let barrier = Arc::new(std::sync::Barrier::new(2));
+    let products = std::thread::spawn({
+        let (app, barrier) = (app.clone(), barrier.clone());
+        move || {
+            barrier.wait();
+            app.build::<Arc<ProductSearch>>().unwrap().index.clone()
+        }
+    });
+    let orders = std::thread::spawn({
+        let (app, barrier) = (app.clone(), barrier.clone());
+        move || {
+            barrier.wait();
+            app.build::<Arc<OrderSearch>>().unwrap().index.clone()
+        }
+    });
+
+    let (products, orders) = (products.join().unwrap(), orders.join().unwrap());
+    assert_eq!(SEARCH_INDEXES.load(Ordering::SeqCst), 1);
+    assert!(Arc::ptr_eq(&products, &orders));
+}
+
+/// Generic, so its `Arc` stays in the scope even though it is `Send + Sync`.
+#[derive(Provider)]
+struct Labeled<T: Default + 'static> {
+    #[provider(default)]
+    _label: T,
+}
+
+#[test]
+fn t_generic_singleton_stays_in_scope() {
+    let app = Arc::new(SyncProviderContext::new());
+    app.scope().build::<Arc<Labeled<u8>>>().unwrap();
+    assert!(!app.contains::<Arc<Labeled<u8>>>());
+}
+
+struct Primary;
+struct Replica;
+
+#[derive(Clone)]
+struct DbUrl(&'static str);
+
+#[derive(Provider)]
+struct ReportRepo {
+    #[provider(named = "primary")]
+    primary: DbUrl,
+    #[provider(tagged = Replica)]
+    replica: DbUrl,
+}
+
+#[test]
+fn t_named_and_tagged_fields() {
+    let app = Arc::new(SyncProviderContext::new());
+    app.insert_tagged::<Replica, _>(DbUrl("replica"));
+    let mut ctx = app
+        .scope()
+        .with_named_instance("primary", DbUrl("primary"))
+        .with_tagged_instance::<Primary, _>(DbUrl("tagged primary"));
+
+    let repo = ctx.build::<ReportRepo>().unwrap();
+    assert_eq!((repo.primary.0, repo.replica.0), ("primary", "replica"));
+    assert_eq!(
+        ctx.get_tagged::<Primary, DbUrl>().unwrap().0,
+        "tagged primary"
+    );
+    assert!(ctx.get::<DbUrl>().is_none());
+
+    ctx.remove_tagged::<Primary, DbUrl>().unwrap();
+    let err = ProviderContext::new().build::<ReportRepo>().err().unwrap();
+    assert_eq!(
+        err.root_cause().to_string(),
+        "Provider::build: Named instance not found. field = primary. type = DbUrl. name = primary"
+    );
+}
+
+#[derive(Clone, Default)]
+struct Timeout(u32);
+
+#[derive(Clone)]
+struct Region(&'static str);
+
+#[derive(Clone)]
+struct Quota(u32);
+
+#[derive(Provider)]
+struct Gateway {
+    #[provider(instance)]
+    host: String,
+    #[provider(default)]
+    timeout: Timeout,
+    #[provider(with = Quota(10))]
+    quota: Quota,
+    #[provider(config)]
+    region: Region,
+    #[provider(named = "primary")]
+    primary: DbUrl,
+    #[provider(tagged = Replica)]
+    replica: DbUrl,
+}
+
+#[test]
+fn t_override_each_field_kind() {
+    let mut ctx = ProviderContext::new();
+    ctx.override_instance(String::from("fake"));
+    ctx.override_instance(Timeout(5));
+    ctx.override_instance(Quota(1));
+    ctx.override_instance(Region("test"));
+    ctx.override_named_instance("primary", DbUrl("fake primary"));
+    ctx.override_tagged_instance::<Replica, _>(DbUrl("fake replica"));
+
+    let gateway = ctx.build::<Gateway>().unwrap();
+    assert_eq!(gateway.host, "fake");
+    assert_eq!((gateway.timeout.0, gateway.quota.0), (5, 1));
+    assert_eq!(gateway.region.0, "test");
+    assert_eq!(gateway.primary.0, "fake primary");
+    assert_eq!(gateway.replica.0, "fake replica");
+}
+
+#[test]
+fn t_overrides_keep_qualifiers_apart() {
+    let mut ctx = ProviderContext::new()
+        .with_instance(String::from("host"))
+        .with_named_instance("primary", DbUrl("primary"))
+        .with_tagged_instance::<Replica, _>(DbUrl("replica"));
+    ctx.override_instance(Region("test"));
+    ctx.override_instance(DbUrl("unqualified"));
+    ctx.override_named_instance("secondary", DbUrl("secondary"));
+    ctx.override_tagged_instance::<Primary, _>(DbUrl("tagged primary"));
+
+    let gateway = ctx.build::<Gateway>().unwrap();
+    assert_eq!(gateway.primary.0, "primary");
+    assert_eq!(gateway.replica.0, "replica");
+}
+
+#[derive(Provider)]
+struct StubNotifier;
+
+#[derive(Provider)]
+struct SmtpNotifier {
+    #[provider(instance)]
+    host: String,
+}
+
+#[derive(Provider)]
+enum Notifier {
+    #[provider(when = |ctx| ctx.get::<bool>().is_some_and(|stub| *stub))]
+    Stub(StubNotifier),
+    Smtp(SmtpNotifier),
+}
+
+#[derive(Provider)]
+#[provider(select = |ctx| ctx.get::<&str>().copied().ok_or_else(|| anyhow::anyhow!("no backend")))]
+enum Backend {
+    Memory,
+    #[provider(rename = "smtp")]
+    Remote {
+        notifier: Notifier,
+    },
+}
+
+#[test]
+fn t_derive_enum_provider() {
+    let notifier = Notifier::provide_with(|ctx| {
+        ctx.insert(true);
+    });
+    assert!(matches!(notifier.unwrap(), Notifier::Stub(_)));
+
+    let backend = Backend::provide_with(|ctx| {
+        ctx.insert("smtp");
+        ctx.insert("mail.local".to_string());
+    });
+    match backend.unwrap() {
+        Backend::Remote {
+            notifier: Notifier::Smtp(smtp),
+        } => assert_eq!(smtp.host, "mail.local"),
+        _ => panic!("expected the smtp backend"),
+    }
+
+    let err = Backend::provide_with(|ctx| {
+        ctx.insert("sqs");
+    })
+    .err()
+    .unwrap();
+    assert_eq!(
+        err.root_cause().to_string(),
+        "Provider::build: unknown variant selected. type = Backend. selected = sqs. expected = memory, smtp"
+    );
+}
+
+#[derive(Provider)]
+struct Dashboard {
+    clock: Lazy<Arc<Clock>>,
+    #[provider(instance)]
+    title: String,
+}
+
+#[test]
+fn t_instance_after_lazy() {
+    let dashboard = Dashboard::provide_with(|ctx| {
+        ctx.insert("stats".to_string());
+    })
+    .unwrap();
+    assert_eq!(dashboard.title, "stats");
+    assert!(dashboard.clock.get().is_ok());
+}
+
+trait Store {
+    fn name(&self) -> &'static str;
+}
+
+#[derive(Provider)]
+struct MemoryStore;
+
+impl Store for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+#[derive(Provider)]
+struct BoxedStoreUser {
+    store: Box<dyn Store>,
+}
+
+#[derive(Provider)]
+struct ArcStoreUser {
+    store: Arc<dyn Store>,
+}
+
+#[derive(Provider)]
+struct RcStoreUser {
+    store: Rc<dyn Store>,
+}
+
+fn store_ctx() -> ProviderContext {
+    let mut ctx = ProviderContext::new();
+    ctx.bind::<dyn Store, MemoryStore>(|b| b);
+    ctx
+}
+
+#[test]
+fn t_bind_boxed_field() {
+    let user = store_ctx().build::<BoxedStoreUser>().unwrap();
+    assert_eq!(user.store.name(), "memory");
+}
+
+#[test]
+fn t_bind_arc_field() {
+    let mut ctx = store_ctx();
+    let user = ctx.build::<ArcStoreUser>().unwrap();
+    assert_eq!(user.store.name(), "memory");
+    let cached = ctx.get::<Arc<dyn Store>>().unwrap();
+    assert!(Arc::ptr_eq(cached, &user.store));
+}
+
+#[test]
+fn t_bind_rc_field() {
+    let mut ctx = store_ctx();
+    let user = ctx.build::<RcStoreUser>().unwrap();
+    assert_eq!(user.store.name(), "memory");
+    let cached = ctx.get::<Rc<dyn Store>>().unwrap();
+    assert!(Rc::ptr_eq(cached, &user.store));
+}
+
+#[test]
+fn t_bind_missing() {
+    let err = ProviderContext::new()
+        .build::<ArcStoreUser>()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Provider::build: path = ArcStoreUser -> Arc<dyn Store> -> Box<dyn Store>"
+    );
+    assert_eq!(
+        err.root_cause().to_string(),
+        "Provider::build: no implementation bound. type = dyn Store"
+    );
+}
@@ -0,0 +1,24 @@
+use lolibaso::Provider;
+
+#[derive(Provider)]
+struct Stub;
+
+#[derive(Provider)]
+struct Smtp;
+
+#[derive(Provider)]
+enum Mailer {
+    Smtp(Smtp),
+    #[provider(when = |_| true)]
+    Stub(Stub),
+}
+
+#[derive(Provider)]
+#[provider(select = |_| Ok("smtp"))]
+enum Transport {
+    #[provider(rename = "smtp")]
+    Primary(Smtp),
+    Smtp(Smtp),
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use lolibaso::flake_id;
+
+flake_id!(UserId, prefix = "");
+flake_id!(OrgId, prefix = "my_org");
+
+fn main() {}
@@ -0,0 +1,9 @@
+use lolibaso::GetConfig;
+
+#[derive(GetConfig)]
+struct AppConfig {
+    primary_url: String,
+    replica_url: String,
+}
+
+fn main() {}
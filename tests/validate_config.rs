@@ -0,0 +1,84 @@
+#![cfg(feature = "regex")]
+
+use lolibaso::ValidateConfig;
+use lolibaso::configs::ValidateConfig as _;
+use serde::Deserialize;
+
+fn check_pool(pool: &PoolConfig) -> Result<(), String> {
+    match pool.min_idle <= pool.max_size {
+        true => Ok(()),
+        false => Err("min_idle must not exceed max_size".into()),
+    }
+}
+
+#[derive(Deserialize, ValidateConfig)]
+#[serde(rename_all = "camelCase")]
+#[validate(custom = check_pool)]
+struct PoolConfig {
+    #[validate(range(min = 1))]
+    max_size: u32,
+    min_idle: u32,
+}
+
+#[derive(Deserialize, ValidateConfig)]
+#[serde(rename_all = "kebab-case")]
+struct DbConfig {
+    #[validate(non_empty, regex = "^[a-z_]+$", regex = "^app_")]
+    schema_name: String,
+    #[serde(rename = "replica")]
+    #[validate(non_empty)]
+    replica_url: Option<String>,
+    #[validate(nested)]
+    connection_pool: PoolConfig,
+}
+
+#[test]
+fn t_derive_validate_config() {
+    let config: DbConfig = serde_json::from_str(
+        r#"{"schema-name": "Main", "replica": "", "connection-pool": {"maxSize": 0, "minIdle": 1}}"#,
+    )
+    .unwrap();
+    let err = config.validate().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "5 config violation(s); \
+         schema-name: must match `^[a-z_]+$`; \
+         schema-name: must match `^app_`; \
+         replica: must not be empty; \
+         connection-pool.maxSize: must be at least 1; \
+         connection-pool: min_idle must not exceed max_size"
+    );
+
+    let config = DbConfig {
+        schema_name: "app_main".into(),
+        replica_url: None,
+        connection_pool: PoolConfig {
+            max_size: 4,
+            min_idle: 1,
+        },
+    };
+    assert!(config.validate().is_ok());
+}
+
+#[derive(Deserialize, ValidateConfig)]
+struct Limits<P: lolibaso::configs::ValidateConfig> {
+    #[validate(range(min = 1, max = 100))]
+    rate: u32,
+    #[validate(nested)]
+    pool: P,
+}
+
+#[test]
+fn t_derive_validate_config_generic() {
+    let limits = Limits {
+        rate: 0,
+        pool: PoolConfig {
+            max_size: 0,
+            min_idle: 0,
+        },
+    };
+    assert_eq!(
+        limits.validate().unwrap_err().to_string(),
+        "2 config violation(s); rate: must be at least 1; pool.maxSize: must be at least 1"
+    );
+}